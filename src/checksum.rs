@@ -0,0 +1,136 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use cargo::core::Package;
+use md5::Context;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The checksums to provide for the crates fetched from a registry
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Md5,
+    Sha256,
+    Both,
+}
+
+impl Kind {
+    /// the values accepted for `--checksum`
+    pub const VARIANTS: &'static [&'static str] = &["md5", "sha256", "both"];
+
+    pub fn md5(self) -> bool {
+        self != Kind::Sha256
+    }
+
+    pub fn sha256(self) -> bool {
+        self != Kind::Md5
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Kind, String> {
+        match s {
+            "md5" => Ok(Kind::Md5),
+            "sha256" => Ok(Kind::Sha256),
+            "both" => Ok(Kind::Both),
+            _ => Err(format!("'{}' is not one of md5, sha256 or both", s)),
+        }
+    }
+}
+
+/// For a given file at path `file`, generate the MD5 sum
+pub fn file_md5<P: AsRef<Path>>(file: P) -> Result<String, io::Error> {
+    let mut file = File::open(file)?;
+    let mut context = Context::new();
+
+    io::copy(&mut file, &mut context)?;
+    Ok(format!("{:x}", context.compute()))
+}
+
+/// Generate the MD5 sum of `data`
+pub fn md5(data: &[u8]) -> String {
+    format!("{:x}", md5::compute(data))
+}
+
+/// For a given file at path `file`, generate the SHA256 sum
+pub fn file_sha256<P: AsRef<Path>>(file: P) -> Result<String, io::Error> {
+    let mut file = File::open(file)?;
+    let mut hasher = Sha256::new();
+
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Path to the downloaded `.crate` file of a registry package, which cargo
+/// keeps in its registry cache next to the directory it extracted the
+/// package into, `<registry>/src/<index>/<name>-<version>`, as
+/// `<registry>/cache/<index>/<name>-<version>.crate`. Crates that weren't
+/// downloaded, such as ones replaced by a directory source, have none.
+pub fn crate_file(pkg: &Package) -> Option<PathBuf> {
+    let root = pkg.root();
+    let index = root.parent()?;
+    let file = index
+        .parent()?
+        .parent()?
+        .join("cache")
+        .join(index.file_name()?)
+        .join(format!("{}.crate", root.file_name()?.to_string_lossy()));
+    Some(file).filter(|file| file.is_file())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cargo::core::{Shell, SourceId};
+    use cargo::{ops, Config};
+
+    #[test]
+    fn kind() {
+        assert_eq!("md5".parse(), Ok(Kind::Md5));
+        assert_eq!("both".parse(), Ok(Kind::Both));
+        assert!("sha1".parse::<Kind>().is_err());
+        assert!(Kind::Both.md5() && Kind::Both.sha256());
+        assert!(!Kind::Sha256.md5());
+    }
+
+    #[test]
+    fn downloaded_crate() {
+        let tmp = crate::testing::temp_dir();
+        let registry = tmp.path().join("registry");
+        let dir = registry.join("src").join("mirror-1234").join("foo-0.1.0");
+        crate::testing::write_crate(&dir, "foo", "");
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let source_id = SourceId::for_path(&dir).unwrap();
+        let (pkg, _) = ops::read_package(&dir.join("Cargo.toml"), source_id, &config).unwrap();
+        assert_eq!(crate_file(&pkg), None);
+
+        let cache = registry.join("cache").join("mirror-1234");
+        std::fs::create_dir_all(&cache).unwrap();
+        std::fs::write(cache.join("foo-0.1.0.crate"), "").unwrap();
+        assert_eq!(crate_file(&pkg), Some(cache.join("foo-0.1.0.crate")));
+    }
+
+    #[test]
+    fn file_sums() {
+        let tmp = crate::testing::temp_dir();
+        let file = tmp.path().join("hello.txt");
+        std::fs::write(&file, "hello\n").unwrap();
+        assert_eq!(file_md5(&file).unwrap(), "b1946ac92492d2347c6235b4d2611184");
+        assert_eq!(
+            file_sha256(&file).unwrap(),
+            "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Maps the native libraries crates declare with `links` to the recipes
+//! that provide them so they can be added to DEPENDS.
+
+use anyhow::anyhow;
+use cargo::util::CargoResult;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// The recipes providing the native libraries commonly linked by -sys crates,
+/// keyed by the `links` value
+const COMMON_LINKS: &[(&str, &str)] = &[
+    ("bzip2", "bzip2"),
+    ("curl", "curl"),
+    ("dbus", "dbus"),
+    ("git2", "libgit2"),
+    ("lzma", "xz"),
+    ("openssl", "openssl"),
+    ("pq", "postgresql"),
+    ("sqlite3", "sqlite3"),
+    ("ssh2", "libssh2"),
+    ("udev", "udev"),
+    ("z", "zlib"),
+    ("zstd", "zstd"),
+];
+
+/// The native recipes providing the host tools used by the build scripts
+/// of crates that have a build dependency on the crate driving them. The
+/// C compiler used through `cc` is always there so it isn't needed.
+const COMMON_BUILD_TOOLS: &[(&str, &str)] = &[
+    ("bindgen", "clang-native"),
+    ("cmake", "cmake-native"),
+    ("pkg-config", "pkgconfig-native"),
+];
+
+/// table of the DEPENDS map holding the overrides for build tools
+const BUILD_TOOLS_TABLE: &str = "build-tools";
+
+/// Provides the mapping of `links` values to recipes, starting from the
+/// built-in one and applying the overrides from the TOML file at `path`,
+/// which holds `links = "recipe"` pairs
+pub fn links_map(path: Option<&Path>) -> CargoResult<BTreeMap<String, String>> {
+    let overrides = match path {
+        Some(path) => read_map(path)?,
+        None => toml::value::Table::new(),
+    };
+    let overrides = overrides
+        .iter()
+        .filter(|&(key, _)| key != BUILD_TOOLS_TABLE)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    apply(COMMON_LINKS, &overrides, path)
+}
+
+/// Provides the mapping of build dependencies on crates that drive host
+/// tools to the native recipes providing them, starting from the built-in
+/// one and applying the overrides in the `[build-tools]` table of the TOML
+/// file at `path`, which holds `crate = "recipe"` pairs
+pub fn build_tools_map(path: Option<&Path>) -> CargoResult<BTreeMap<String, String>> {
+    let overrides = match path {
+        Some(path) => match read_map(path)?.remove(BUILD_TOOLS_TABLE) {
+            Some(toml::Value::Table(table)) => table,
+            Some(_) => {
+                return Err(anyhow!(
+                    "DEPENDS map '{}' must have a table for [{}]",
+                    path.display(),
+                    BUILD_TOOLS_TABLE
+                ))
+            }
+            None => toml::value::Table::new(),
+        },
+        None => toml::value::Table::new(),
+    };
+    apply(COMMON_BUILD_TOOLS, &overrides, path)
+}
+
+/// Reads the DEPENDS map at `path`
+fn read_map(path: &Path) -> CargoResult<toml::value::Table> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Unable to read DEPENDS map '{}': {}", path.display(), e))?;
+    match contents
+        .parse::<toml::Value>()
+        .map_err(|e| anyhow!("Unable to parse DEPENDS map '{}': {}", path.display(), e))?
+    {
+        toml::Value::Table(table) => Ok(table),
+        _ => Err(anyhow!("DEPENDS map '{}' must be a table", path.display())),
+    }
+}
+
+/// Applies the `overrides` read from the DEPENDS map at `path` to the
+/// built-in mapping `defaults`
+fn apply(
+    defaults: &[(&str, &str)],
+    overrides: &toml::value::Table,
+    path: Option<&Path>,
+) -> CargoResult<BTreeMap<String, String>> {
+    let mut map = defaults
+        .iter()
+        .map(|&(key, recipe)| (key.to_string(), recipe.to_string()))
+        .collect::<BTreeMap<_, _>>();
+
+    for (key, recipe) in overrides {
+        let recipe = recipe.as_str().ok_or_else(|| {
+            anyhow!(
+                "DEPENDS map '{}' must map '{}' to a recipe name",
+                path.map_or_else(String::new, |p| p.display().to_string()),
+                key
+            )
+        })?;
+        map.insert(key.clone(), recipe.to_string());
+    }
+
+    Ok(map)
+}
+
+/// Generates the DEPENDS lines for the native libraries in `links`, which
+/// holds the crate and the `links` value it declares. Libraries without a
+/// known recipe are only suggested in a comment.
+pub fn depends(links: &[(String, String)], map: &BTreeMap<String, String>) -> String {
+    let mut known = BTreeSet::new();
+    let mut unknown = vec![];
+    for (krate, lib) in links {
+        match map.get(lib) {
+            Some(recipe) => {
+                known.insert(recipe.as_str());
+            }
+            None => unknown.push(format!(
+                "# DEPENDS += \"{}\" (links = \"{}\" in {})",
+                lib, lib, krate
+            )),
+        }
+    }
+
+    let mut lines = vec![];
+    if !known.is_empty() {
+        lines.push(format!(
+            "DEPENDS += \"{}\"",
+            known.into_iter().collect::<Vec<_>>().join(" ")
+        ));
+    }
+    lines.extend(unknown);
+    lines.join("\n")
+}
+
+/// Generates commented DEPENDS suggestions for the host tools used by the
+/// build scripts in `tools`, which holds the crate and the build dependency
+/// it has on a crate driving a tool
+pub fn build_tools(tools: &[(String, String)], map: &BTreeMap<String, String>) -> String {
+    let mut users = BTreeMap::new();
+    for (krate, tool) in tools {
+        if let Some(recipe) = map.get(tool) {
+            users
+                .entry((recipe.as_str(), tool.as_str()))
+                .or_insert_with(BTreeSet::new)
+                .insert(krate.as_str());
+        }
+    }
+
+    users
+        .into_iter()
+        .map(|((recipe, tool), krates)| {
+            format!(
+                "# DEPENDS += \"{}\" ({} is used by the build of {})",
+                recipe,
+                tool,
+                krates.into_iter().collect::<Vec<_>>().join(", ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn known_links() {
+        let links = vec![
+            ("openssl-sys".to_string(), "openssl".to_string()),
+            ("libz-sys".to_string(), "z".to_string()),
+        ];
+        let map = links_map(None).unwrap();
+        assert_eq!(depends(&links, &map), "DEPENDS += \"openssl zlib\"");
+    }
+
+    #[test]
+    fn unknown_links() {
+        let links = vec![
+            ("openssl-sys".to_string(), "openssl".to_string()),
+            ("foo-sys".to_string(), "foo".to_string()),
+        ];
+        let map = links_map(None).unwrap();
+        assert_eq!(
+            depends(&links, &map),
+            "DEPENDS += \"openssl\"\n# DEPENDS += \"foo\" (links = \"foo\" in foo-sys)"
+        );
+    }
+
+    #[test]
+    fn override_map() {
+        let tmp = crate::testing::temp_dir();
+        let path = tmp.path().join("depends.toml");
+        fs::write(&path, "foo = \"libfoo\"\nopenssl = \"openssl10\"\n").unwrap();
+
+        let links = vec![
+            ("openssl-sys".to_string(), "openssl".to_string()),
+            ("foo-sys".to_string(), "foo".to_string()),
+        ];
+        let map = links_map(Some(&path)).unwrap();
+        assert_eq!(depends(&links, &map), "DEPENDS += \"libfoo openssl10\"");
+    }
+
+    #[test]
+    fn build_tool_suggestions() {
+        let tools = vec![
+            ("openssl-sys".to_string(), "pkg-config".to_string()),
+            ("libgit2-sys".to_string(), "pkg-config".to_string()),
+            ("libgit2-sys".to_string(), "cc".to_string()),
+            ("foo-sys".to_string(), "cmake".to_string()),
+        ];
+        let map = build_tools_map(None).unwrap();
+        assert_eq!(
+            build_tools(&tools, &map),
+            "# DEPENDS += \"cmake-native\" (cmake is used by the build of foo-sys)\n\
+             # DEPENDS += \"pkgconfig-native\" (pkg-config is used by the build of libgit2-sys, openssl-sys)"
+        );
+    }
+
+    #[test]
+    fn override_build_tools() {
+        let tmp = crate::testing::temp_dir();
+        let path = tmp.path().join("depends.toml");
+        fs::write(
+            &path,
+            "foo = \"libfoo\"\n\n[build-tools]\ncc = \"gcc-native\"\n",
+        )
+        .unwrap();
+
+        let tools = vec![("libgit2-sys".to_string(), "cc".to_string())];
+        let map = build_tools_map(Some(&path)).unwrap();
+        assert_eq!(
+            build_tools(&tools, &map),
+            "# DEPENDS += \"gcc-native\" (cc is used by the build of libgit2-sys)"
+        );
+        // the build tools don't end up in the links map
+        let links = links_map(Some(&path)).unwrap();
+        assert_eq!(links.get("foo").map(String::as_str), Some("libfoo"));
+        assert!(!links.contains_key("build-tools"));
+    }
+
+    #[test]
+    fn no_links() {
+        assert_eq!(depends(&[], &links_map(None).unwrap()), "");
+    }
+}
@@ -0,0 +1,181 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! A line based unified diff, used to show how a recipe on disk differs
+//! from the one that would be generated. Recipes are small enough that
+//! the simple longest common subsequence approach is plenty.
+
+/// lines of unchanged context shown around each change
+const CONTEXT: usize = 3;
+
+/// How a line gets from the old text to the new one
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    /// the line at the index of the old text is in both
+    Equal(usize),
+    /// the line at the index of the old text was removed
+    Delete(usize),
+    /// the line at the index of the new text was added
+    Insert(usize),
+}
+
+/// Provides the steps turning the lines `old` into the lines `new`
+fn ops(old: &[&str], new: &[&str]) -> Vec<Op> {
+    // lengths of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push(Op::Equal(i));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            // removals come before additions, like diff(1)
+            ops.push(Op::Delete(i));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(j));
+            j += 1;
+        }
+    }
+    ops
+}
+
+/// Provides the unified diff turning `old` into `new`, labelled with
+/// `old_name` and `new_name`. Nothing is provided when they're the same.
+pub fn unified(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let ops = ops(&old_lines, &new_lines);
+
+    // the ranges of steps making up each hunk, merging changes whose
+    // context would overlap
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for (idx, op) in ops.iter().enumerate() {
+        if let Op::Equal(_) = op {
+            continue;
+        }
+        let start = idx.saturating_sub(CONTEXT);
+        let end = (idx + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        // where the hunk starts in each text
+        let old_start = ops[..start]
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert(_)))
+            .count();
+        let new_start = ops[..start]
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete(_)))
+            .count();
+        let hunk = &ops[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete(_)))
+            .count();
+
+        // an empty range is given as the line before it
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            if old_len == 0 {
+                old_start
+            } else {
+                old_start + 1
+            },
+            old_len,
+            if new_len == 0 {
+                new_start
+            } else {
+                new_start + 1
+            },
+            new_len
+        ));
+        for op in hunk {
+            let line = match *op {
+                Op::Equal(i) => format!(" {}", old_lines[i]),
+                Op::Delete(i) => format!("-{}", old_lines[i]),
+                Op::Insert(j) => format!("+{}", new_lines[j]),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same() {
+        assert_eq!(unified("a", "b", "x\ny\n", "x\ny\n"), "");
+    }
+
+    #[test]
+    fn changed_line() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+        assert_eq!(
+            unified("old.bb", "new.bb", old, new),
+            "--- old.bb\n+++ new.bb\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+
+    #[test]
+    fn separate_hunks() {
+        let old = (1..=20).map(|n| format!("{}\n", n)).collect::<String>();
+        let new = (1..=20)
+            .filter(|&n| n != 19)
+            .map(|n| match n {
+                2 => "two\n".to_string(),
+                n => format!("{}\n", n),
+            })
+            .collect::<String>();
+        assert_eq!(
+            unified("a", "b", &old, &new),
+            "--- a\n+++ b\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -16,5 +16,4 @@\n 16\n 17\n 18\n-19\n 20\n"
+        );
+    }
+
+    #[test]
+    fn added_to_empty() {
+        assert_eq!(
+            unified("a", "b", "", "x\n"),
+            "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+x\n"
+        );
+    }
+}
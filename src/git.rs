@@ -8,14 +8,16 @@
  * except according to those terms.
  */
 
+use crate::template;
+use cargo::core::source::GitReference;
 use cargo::util::{CargoResult, CargoResultExt};
-use cargo::Config;
 use anyhow::anyhow;
 use git2::{self, Repository};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::default::Default;
 use std::fmt::{self, Display};
+use std::path::Path;
 
 /// basic pattern to match ssh style remote URLs
 /// so that they can be fixed up
@@ -26,18 +28,25 @@ lazy_static! {
     static ref SSH_STYLE_REMOTE: Regex = Regex::new(SSH_STYLE_REMOTE_STR).unwrap();
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// branches a git dependency can track without the branch being named
+pub const DEFAULT_BRANCHES: &[&str] = &["master", "main"];
+
+/// where the git fetcher unpacks the project's repo
+const PROJECT_CHECKOUT_DIR: &str = "${WORKDIR}/git";
+
+/// transports the git fetcher can be told to use with `protocol=`
+pub const PROTOCOLS: &[&str] = &["git", "http", "https", "ssh", "file", "rsync"];
+
+/// revision used to follow the head of a branch
+const AUTOREV: &str = "${AUTOREV}";
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub enum GitPrefix {
+    #[default]
     Git,
     GitSubmodule,
 }
 
-impl Default for GitPrefix {
-    fn default() -> GitPrefix {
-        GitPrefix::Git
-    }
-}
-
 impl Display for GitPrefix {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(
@@ -51,12 +60,50 @@ impl Display for GitPrefix {
     }
 }
 
-/// converts a GIT URL to a Yocto GIT URL
-pub fn git_to_yocto_git_url(url: &str, name: Option<&str>, prefix: GitPrefix) -> String {
+/// Splits a git source URL as Cargo.lock records it, such as
+/// `https://example.com/foo.git?branch=next#<rev>`, into the URL to fetch,
+/// the branch from the query and the revision from the fragment
+pub fn split_source_url(url: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (url, rev) = match url.find('#') {
+        Some(idx) => (&url[..idx], Some(&url[idx + 1..])),
+        None => (url, None),
+    };
+    let (url, query) = match url.find('?') {
+        Some(idx) => (&url[..idx], Some(&url[idx + 1..])),
+        None => (url, None),
+    };
+    let branch = query.and_then(|query| {
+        query
+            .split('&')
+            .find(|param| param.starts_with("branch="))
+            .map(|param| &param["branch=".len()..])
+    });
+
+    (url, branch, rev.filter(|rev| !rev.is_empty()))
+}
+
+/// converts a GIT URL to a Yocto GIT URL. `subpath` limits the checkout to
+/// the directory of the repo that a crate lives in and `branch` is the branch
+/// to fetch from when tracking the head of a branch other than master.
+/// `destdir` is the directory under `${WORKDIR}` it's unpacked into.
+pub fn git_to_yocto_git_url(
+    url: &str,
+    name: Option<&str>,
+    prefix: GitPrefix,
+    subpath: Option<&str>,
+    branch: Option<&str>,
+    destdir: Option<&str>,
+) -> String {
+    // a branch or revision on the URL itself isn't understood by the
+    // fetcher, the branch becomes a parameter and the revision is SRCREV
+    let (url, url_branch, _) = split_source_url(url);
+    let branch = branch.or(url_branch);
+
     // check if its a git@github.com:cardoe/cargo-bitbake.git style URL
-    // and fix it up if it is
-    let fixed_url = if SSH_STYLE_REMOTE.is_match(url) {
-        format!("ssh://{}", url.replace(":", "/"))
+    // and fix it up if it is. URLs with a scheme, such as
+    // ssh://git@example.com:2222/foo.git, are already fine
+    let fixed_url = if !url.contains("://") && SSH_STYLE_REMOTE.is_match(url) {
+        format!("ssh://{}", url.replacen(":", "/", 1))
     } else {
         url.to_string()
     };
@@ -66,7 +113,7 @@ pub fn git_to_yocto_git_url(url: &str, name: Option<&str>, prefix: GitPrefix) ->
     // ssh://... -> git://...;protocol=ssh
     // and append metadata necessary for Yocto to generate
     // data for Cargo to understand
-    let yocto_url = match fixed_url.split_at(fixed_url.find(':').unwrap()) {
+    let yocto_url = match fixed_url.split_at(fixed_url.find(':').unwrap_or(0)) {
         (proto @ "ssh", rest) | (proto @ "http", rest) | (proto @ "https", rest) => {
             format!("{}{};protocol={}", prefix, rest, proto)
         }
@@ -74,28 +121,156 @@ pub fn git_to_yocto_git_url(url: &str, name: Option<&str>, prefix: GitPrefix) ->
     };
 
     // by default bitbake only look for SHAs and refs on the master branch.
-    let yocto_url = format!("{};nobranch=1", yocto_url);
+    let yocto_url = if let Some(branch) = branch {
+        format!("{};branch={}", yocto_url, branch)
+    } else {
+        format!("{};nobranch=1", yocto_url)
+    };
+
+    let yocto_url = if let Some(name) = name {
+        let destsuffix = match destdir {
+            Some(dir) => format!("{}/{}", dir.trim_end_matches('/'), name),
+            None => name.to_string(),
+        };
+        format!("{};name={};destsuffix={}", yocto_url, name, destsuffix)
+    } else {
+        yocto_url
+    };
 
-    if let Some(name) = name {
-        format!("{};name={};destsuffix={}", yocto_url, name, name)
+    if let Some(subpath) = subpath {
+        format!("{};subpath={}", yocto_url, subpath)
     } else {
         yocto_url
     }
 }
 
+/// Replaces the transport the fetcher uses for the Yocto GIT URL `url`,
+/// which otherwise follows the scheme of the URL it was made from
+pub fn with_protocol(url: &str, protocol: &str) -> String {
+    let mut parts = url.split(';');
+    let mut fixed = vec![parts.next().unwrap_or(""), ""];
+    fixed.extend(parts.filter(|param| !param.starts_with("protocol=")));
+    let protocol = format!("protocol={}", protocol);
+    fixed[1] = &protocol;
+    fixed.join(";")
+}
+
+/// Determines the SRCREV to use for a git dependency referenced by
+/// `reference` along with the branch, if any, that has to be given to the
+/// fetcher. Branches in `default_branches` don't need to be named. A
+/// branch uses the `precise` commit Cargo.lock pinned it to, unless there
+/// is none or `autorev` asks to follow the head of the branch. A tag is
+/// used as it is unless `pin_tags` asks for the `precise` commit, since
+/// a tag can be moved.
+pub fn dependency_rev<'a>(
+    reference: &'a GitReference,
+    precise: Option<&str>,
+    default_branches: &[String],
+    autorev: bool,
+    pin_tags: bool,
+) -> (String, Option<&'a str>) {
+    let rev = match precise {
+        Some(precise) if !autorev => precise.to_owned(),
+        _ => AUTOREV.into(),
+    };
+
+    match *reference {
+        GitReference::Tag(ref s) => match precise {
+            Some(precise) if pin_tags => (precise.to_owned(), None),
+            _ => (s.to_owned(), None),
+        },
+        GitReference::Rev(ref s) => (s.to_owned(), None),
+        GitReference::Branch(ref s) => {
+            if default_branches.iter().any(|b| b == s) {
+                (rev, None)
+            } else {
+                (rev, Some(s.as_str()))
+            }
+        }
+        GitReference::DefaultBranch => (rev, None),
+    }
+}
+
+/// Provides the PV addition for a project checked out at `rev` that isn't
+/// a tag, carrying the first `len` characters of the revision. Nothing is
+/// added without a revision.
+pub fn pv_append(rev: &str, len: usize) -> String {
+    if rev.is_empty() {
+        return String::new();
+    }
+
+    let abbrev = rev.get(..len).unwrap_or(rev);
+    format!("PV_append = \".AUTOINC+{}\"", abbrev)
+}
+
+/// Provides the SRCREV_FORMAT combining the revision of the project, which
+/// is the unnamed `default` entry of SRC_URI, with those of the git
+/// dependencies called `names`
+pub fn srcrev_format<S: AsRef<str>>(names: &[S]) -> String {
+    let mut format = String::from("default");
+    for name in names {
+        format.push('_');
+        format.push_str(name.as_ref());
+    }
+    format!("SRCREV_FORMAT = \"{}\"", format)
+}
+
+/// Inspects the checkout cargo made of a git dependency whose Cargo.toml is
+/// in `crate_root`, providing the prefix needed to fetch it (so that any
+/// submodules are fetched too) and the sub directory of the repo the crate
+/// is in, if it isn't at the top level
+pub fn dependency_checkout(crate_root: &Path) -> (GitPrefix, Option<String>) {
+    let repo = match Repository::discover(crate_root) {
+        Ok(repo) => repo,
+        Err(_) => return (GitPrefix::default(), None),
+    };
+
+    let prefix = match repo.submodules() {
+        Ok(ref submodules) if !submodules.is_empty() => GitPrefix::GitSubmodule,
+        _ => GitPrefix::Git,
+    };
+
+    let subpath = repo.workdir().and_then(|workdir| subdir(workdir, crate_root));
+
+    (prefix, subpath)
+}
+
+/// Provides the sub directory of the repo checked out at `workdir` that
+/// `path` is, if it isn't the top level of the repo
+fn subdir(workdir: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(workdir)
+        .ok()
+        .filter(|rel| rel.components().next().is_some())
+        .map(template::recipe_path)
+}
+
+/// Provides where the project's git checkout is unpacked, down into the
+/// sub directory `subdir` of the repo that it's in
+pub fn project_src_dir(subdir: &str) -> String {
+    if subdir.is_empty() {
+        PROJECT_CHECKOUT_DIR.to_string()
+    } else {
+        format!("{}/{}", PROJECT_CHECKOUT_DIR, subdir)
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ProjectRepo {
     pub uri: String,
     pub branch: String,
     pub rev: String,
     pub tag: bool,
+    /// sub directory of the repo the project is in, empty when the
+    /// project is the whole repo
+    pub subdir: String,
 }
 
 impl ProjectRepo {
-    /// Attempts to guess at the upstream repo this project can be fetched from
-    pub fn new(config: &Config) -> CargoResult<ProjectRepo> {
-        let repo = Repository::discover(config.cwd())
+    /// Attempts to guess at the upstream repo the project at `path` can be fetched from
+    pub fn new(path: &Path) -> CargoResult<ProjectRepo> {
+        let repo = Repository::discover(path)
             .chain_err(|| "Unable to determine git repo for this project")?;
+        let subdir = Self::subdir_of(&repo, path);
 
         let remote = repo
             .find_remote("origin")
@@ -113,7 +288,7 @@ impl ProjectRepo {
         let uri = remote
             .url()
             .ok_or_else(|| anyhow!("No URL for remote 'origin'"))?;
-        let uri = git_to_yocto_git_url(uri, None, prefix);
+        let uri = git_to_yocto_git_url(uri, None, prefix, None, None, None);
 
         let head = repo.head().chain_err(|| "Unable to find HEAD")?;
         let branch = head
@@ -136,9 +311,26 @@ impl ProjectRepo {
             branch: branch.to_string(),
             rev: rev.to_string(),
             tag: Self::rev_is_tag(&repo, &rev),
+            subdir,
         })
     }
 
+    /// Provides the sub directory of its repo the project at `path` is in,
+    /// which is needed even when the repo isn't used to fetch the project
+    pub fn subdir(path: &Path) -> String {
+        Repository::discover(path)
+            .map(|repo| Self::subdir_of(&repo, path))
+            .unwrap_or_default()
+    }
+
+    fn subdir_of(repo: &git2::Repository, path: &Path) -> String {
+        // the repo's paths are canonical so the project's must be too
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        repo.workdir()
+            .and_then(|workdir| subdir(workdir, &path))
+            .unwrap_or_default()
+    }
+
     /// attempts to determine if the specific revision is a tag
     fn rev_is_tag(repo: &git2::Repository, rev: &git2::Oid) -> bool {
         // gather up all the tags, if there are none then its not a tag
@@ -150,7 +342,7 @@ impl ProjectRepo {
         // walk through all the tags and resolve them to their commitish
         // return true if we find a tag that matches our revision
         tags.iter()
-            .filter_map(|tag| tag)
+            .flatten()
             .filter_map(|tag| repo.revparse_single(tag).ok())
             .filter_map(|tag| tag.peel(git2::ObjectType::Commit).ok())
             .any(|t| t.id() == *rev)
@@ -164,7 +356,7 @@ mod test {
     #[test]
     fn remote_http() {
         let repo = "http://github.com/rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, None, None);
         assert_eq!(url,
                 "git://github.com/rust-lang/cargo.git;protocol=http;nobranch=1;name=cargo;destsuffix=cargo");
     }
@@ -172,7 +364,7 @@ mod test {
     #[test]
     fn remote_https() {
         let repo = "https://github.com/rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, None, None);
         assert_eq!(url,
                 "git://github.com/rust-lang/cargo.git;protocol=https;nobranch=1;name=cargo;destsuffix=cargo");
     }
@@ -180,15 +372,108 @@ mod test {
     #[test]
     fn remote_ssh() {
         let repo = "git@github.com:rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, None, None);
         assert_eq!(url,
                 "git://git@github.com/rust-lang/cargo.git;protocol=ssh;nobranch=1;name=cargo;destsuffix=cargo");
     }
 
+    #[test]
+    fn remote_gitlab() {
+        let repo = "https://gitlab.com/gitlab-org/gitlab-foss.git";
+        let url = git_to_yocto_git_url(repo, Some("foss"), GitPrefix::Git, None, None, None);
+        assert_eq!(url,
+                "git://gitlab.com/gitlab-org/gitlab-foss.git;protocol=https;nobranch=1;name=foss;destsuffix=foss");
+    }
+
+    #[test]
+    fn remote_bitbucket() {
+        let repo = "https://bitbucket.org/team/repo.git";
+        let url = git_to_yocto_git_url(repo, Some("repo"), GitPrefix::Git, None, None, None);
+        assert_eq!(url,
+                "git://bitbucket.org/team/repo.git;protocol=https;nobranch=1;name=repo;destsuffix=repo");
+    }
+
+    #[test]
+    fn remote_self_hosted() {
+        let repo = "https://git.example.com/group/sub/repo";
+        let url = git_to_yocto_git_url(repo, Some("repo"), GitPrefix::Git, None, None, None);
+        assert_eq!(url,
+                "git://git.example.com/group/sub/repo;protocol=https;nobranch=1;name=repo;destsuffix=repo");
+    }
+
+    #[test]
+    fn remote_ssh_scheme() {
+        let repo = "ssh://git@git.sr.ht:2222/~user/repo";
+        let url = git_to_yocto_git_url(repo, Some("repo"), GitPrefix::Git, None, None, None);
+        assert_eq!(url,
+                "git://git@git.sr.ht:2222/~user/repo;protocol=ssh;nobranch=1;name=repo;destsuffix=repo");
+    }
+
+    #[test]
+    fn remote_ssh_self_hosted() {
+        let repo = "git@git.example.com:group/repo.git";
+        let url = git_to_yocto_git_url(repo, None, GitPrefix::Git, None, None, None);
+        assert_eq!(
+            url,
+            "git://git@git.example.com/group/repo.git;protocol=ssh;nobranch=1"
+        );
+    }
+
+    #[test]
+    fn remote_git_scheme() {
+        let repo = "git://git.example.com/group/repo.git";
+        let url = git_to_yocto_git_url(repo, None, GitPrefix::Git, None, None, None);
+        assert_eq!(url, "git://git.example.com/group/repo.git;nobranch=1");
+    }
+
+    #[test]
+    fn protocol_override() {
+        let url = "git://github.com/rust-lang/cargo.git;protocol=https;nobranch=1;name=cargo";
+        assert_eq!(
+            with_protocol(url, "ssh"),
+            "git://github.com/rust-lang/cargo.git;protocol=ssh;nobranch=1;name=cargo"
+        );
+
+        let url = "git://git.example.com/group/repo.git;nobranch=1";
+        assert_eq!(
+            with_protocol(url, "https"),
+            "git://git.example.com/group/repo.git;protocol=https;nobranch=1"
+        );
+    }
+
+    #[test]
+    fn remote_https_destdir() {
+        let repo = "https://github.com/rust-lang/cargo.git";
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, None, Some("git/"));
+        assert_eq!(url,
+                "git://github.com/rust-lang/cargo.git;protocol=https;nobranch=1;name=cargo;destsuffix=git/cargo");
+    }
+
+    #[test]
+    fn source_url_parts() {
+        let url = "https://github.com/rust-lang/cargo.git?branch=stable#0123456789abcdef";
+        assert_eq!(
+            split_source_url(url),
+            ("https://github.com/rust-lang/cargo.git", Some("stable"), Some("0123456789abcdef"))
+        );
+        assert_eq!(
+            split_source_url("https://github.com/rust-lang/cargo.git"),
+            ("https://github.com/rust-lang/cargo.git", None, None)
+        );
+    }
+
+    #[test]
+    fn remote_https_query_fragment() {
+        let repo = "https://github.com/rust-lang/cargo.git?branch=stable#0123456789abcdef";
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, None, None);
+        assert_eq!(url,
+                "git://github.com/rust-lang/cargo.git;protocol=https;branch=stable;name=cargo;destsuffix=cargo");
+    }
+
     #[test]
     fn remote_http_nosuffix() {
         let repo = "http://github.com/rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, None, GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, None, GitPrefix::Git, None, None, None);
         assert_eq!(
             url,
             "git://github.com/rust-lang/cargo.git;protocol=http;nobranch=1"
@@ -198,7 +483,7 @@ mod test {
     #[test]
     fn remote_https_nosuffix() {
         let repo = "https://github.com/rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, None, GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, None, GitPrefix::Git, None, None, None);
         assert_eq!(
             url,
             "git://github.com/rust-lang/cargo.git;protocol=https;nobranch=1"
@@ -208,7 +493,7 @@ mod test {
     #[test]
     fn remote_ssh_nosuffix() {
         let repo = "git@github.com:rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, None, GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, None, GitPrefix::Git, None, None, None);
         assert_eq!(
             url,
             "git://git@github.com/rust-lang/cargo.git;protocol=ssh;nobranch=1"
@@ -218,7 +503,7 @@ mod test {
     #[test]
     fn cargo_http() {
         let repo = "http://github.com/rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, None, None);
         assert_eq!(url,
                 "git://github.com/rust-lang/cargo.git;protocol=http;nobranch=1;name=cargo;destsuffix=cargo");
     }
@@ -226,7 +511,7 @@ mod test {
     #[test]
     fn cargo_https() {
         let repo = "https://github.com/rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, None, None);
         assert_eq!(url,
                 "git://github.com/rust-lang/cargo.git;protocol=https;nobranch=1;name=cargo;destsuffix=cargo");
     }
@@ -234,7 +519,7 @@ mod test {
     #[test]
     fn cargo_ssh() {
         let repo = "ssh://git@github.com/rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git);
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, None, None);
         assert_eq!(url,
                 "git://git@github.com/rust-lang/cargo.git;protocol=ssh;nobranch=1;name=cargo;destsuffix=cargo");
     }
@@ -242,8 +527,148 @@ mod test {
     #[test]
     fn remote_ssh_with_submodules() {
         let repo = "git@github.com:rust-lang/cargo.git";
-        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::GitSubmodule);
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::GitSubmodule, None, None, None);
         assert_eq!(url,
                 "gitsm://git@github.com/rust-lang/cargo.git;protocol=ssh;nobranch=1;name=cargo;destsuffix=cargo");
     }
+
+    #[test]
+    fn remote_https_monorepo() {
+        let repo = "https://github.com/rust-lang/cargo.git";
+        let url = git_to_yocto_git_url(
+            repo,
+            Some("crates-io"),
+            GitPrefix::Git,
+            Some("crates/crates-io"),
+            None,
+            None,
+        );
+        assert_eq!(url,
+                "git://github.com/rust-lang/cargo.git;protocol=https;nobranch=1;name=crates-io;destsuffix=crates-io;subpath=crates/crates-io");
+    }
+
+    #[test]
+    fn remote_https_monorepo_with_submodules() {
+        let repo = "https://github.com/rust-lang/cargo.git";
+        let url = git_to_yocto_git_url(
+            repo,
+            Some("crates-io"),
+            GitPrefix::GitSubmodule,
+            Some("crates/crates-io"),
+            None,
+            None,
+        );
+        assert_eq!(url,
+                "gitsm://github.com/rust-lang/cargo.git;protocol=https;nobranch=1;name=crates-io;destsuffix=crates-io;subpath=crates/crates-io");
+    }
+
+    #[test]
+    fn remote_https_branch() {
+        let repo = "https://github.com/rust-lang/cargo.git";
+        let url = git_to_yocto_git_url(repo, Some("cargo"), GitPrefix::Git, None, Some("stable"), None);
+        assert_eq!(url,
+                "git://github.com/rust-lang/cargo.git;protocol=https;branch=stable;name=cargo;destsuffix=cargo");
+    }
+
+    fn default_branches() -> Vec<String> {
+        DEFAULT_BRANCHES.iter().map(|b| b.to_string()).collect()
+    }
+
+    #[test]
+    fn rev_master_branch() {
+        let reference = GitReference::Branch("master".into());
+        let rev = dependency_rev(&reference, None, &default_branches(), false, false);
+        assert_eq!(rev, ("${AUTOREV}".into(), None));
+    }
+
+    #[test]
+    fn rev_main_branch() {
+        let reference = GitReference::Branch("main".into());
+        let rev = dependency_rev(&reference, None, &default_branches(), false, false);
+        assert_eq!(rev, ("${AUTOREV}".into(), None));
+    }
+
+    #[test]
+    fn rev_other_branch() {
+        let reference = GitReference::Branch("stable".into());
+        let rev = dependency_rev(&reference, None, &default_branches(), false, false);
+        assert_eq!(rev, ("${AUTOREV}".into(), Some("stable")));
+
+        let rev = dependency_rev(&reference, None, &["stable".into()], false, false);
+        assert_eq!(rev, ("${AUTOREV}".into(), None));
+    }
+
+    #[test]
+    fn rev_tag() {
+        let reference = GitReference::Tag("v1.0.0".into());
+        let rev = dependency_rev(&reference, None, &default_branches(), false, false);
+        assert_eq!(rev, ("v1.0.0".into(), None));
+    }
+
+    #[test]
+    fn rev_pinned_tag() {
+        let precise = "0123456789abcdef0123456789abcdef01234567";
+        let reference = GitReference::Tag("v1.0.0".into());
+        let rev = dependency_rev(&reference, Some(precise), &default_branches(), false, false);
+        assert_eq!(rev, ("v1.0.0".into(), None));
+
+        let rev = dependency_rev(&reference, Some(precise), &default_branches(), false, true);
+        assert_eq!(rev, (precise.into(), None));
+
+        // nothing to pin to without Cargo.lock
+        let rev = dependency_rev(&reference, None, &default_branches(), false, true);
+        assert_eq!(rev, ("v1.0.0".into(), None));
+    }
+
+    #[test]
+    fn rev_locked_branch() {
+        let precise = "0123456789abcdef0123456789abcdef01234567";
+        let reference = GitReference::DefaultBranch;
+        let rev = dependency_rev(&reference, Some(precise), &default_branches(), false, false);
+        assert_eq!(rev, (precise.into(), None));
+
+        let reference = GitReference::Branch("stable".into());
+        let rev = dependency_rev(&reference, Some(precise), &default_branches(), false, false);
+        assert_eq!(rev, (precise.into(), Some("stable")));
+    }
+
+    #[test]
+    fn rev_locked_autorev() {
+        let precise = "0123456789abcdef0123456789abcdef01234567";
+        let reference = GitReference::DefaultBranch;
+        let rev = dependency_rev(&reference, Some(precise), &default_branches(), true, false);
+        assert_eq!(rev, ("${AUTOREV}".into(), None));
+    }
+
+    #[test]
+    fn pv_append_length() {
+        let rev = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(pv_append(rev, 10), "PV_append = \".AUTOINC+0123456789\"");
+        assert_eq!(pv_append(rev, 7), "PV_append = \".AUTOINC+0123456\"");
+    }
+
+    #[test]
+    fn pv_append_short_rev() {
+        assert_eq!(pv_append("abc", 10), "PV_append = \".AUTOINC+abc\"");
+        assert_eq!(pv_append("", 10), "");
+    }
+
+    #[test]
+    fn project_subdir() {
+        let workdir = Path::new("/src/monorepo");
+        let path = Path::new("/src/monorepo/tools/frobnicate");
+        let rel = subdir(workdir, path).unwrap();
+        assert_eq!(rel, "tools/frobnicate");
+        assert_eq!(project_src_dir(&rel), "${WORKDIR}/git/tools/frobnicate");
+
+        assert_eq!(subdir(workdir, workdir), None);
+        assert_eq!(project_src_dir(""), "${WORKDIR}/git");
+    }
+
+    #[test]
+    fn srcrev_format_once() {
+        let format = srcrev_format(&["alpha", "beta", "gamma"]);
+        assert_eq!(format, "SRCREV_FORMAT = \"default_alpha_beta_gamma\"");
+        assert_eq!(format.matches("SRCREV_FORMAT").count(), 1);
+    }
 }
@@ -0,0 +1,76 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! The machine readable description of the dependencies that went into
+//! the generated recipes, written out with `--emit-json`. The layout is
+//!
+//! ```json
+//! {
+//!   "packages": [
+//!     {
+//!       "name": "cargo-bitbake",
+//!       "version": "0.3.15",
+//!       "dependencies": [
+//!         {
+//!           "name": "anyhow",
+//!           "version": "1.0.40",
+//!           "source": "registry",
+//!           "registry": "crates.io",
+//!           "url": "crate://crates.io/anyhow/1.0.40"
+//!         }
+//!       ],
+//!       "git_revisions": { "some-git-dep": "<rev>" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! where `source` is one of `registry`, `git` or `other` and `registry`
+//! is only set for crates that come from a registry.
+
+use crate::output;
+use anyhow::anyhow;
+use cargo::util::CargoResult;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A crate the package depends on
+#[derive(Debug, Serialize)]
+pub struct Dependency {
+    pub name: String,
+    pub version: String,
+    pub source: &'static str,
+    pub registry: Option<String>,
+    pub url: String,
+}
+
+/// The dependencies of a package a recipe was generated for
+#[derive(Debug, Default, Serialize)]
+pub struct PackageDeps {
+    pub name: String,
+    pub version: String,
+    pub dependencies: Vec<Dependency>,
+    pub git_revisions: BTreeMap<String, String>,
+}
+
+/// The full document written out
+#[derive(Debug, Serialize)]
+pub struct Document {
+    pub packages: Vec<PackageDeps>,
+}
+
+/// Writes `doc` as pretty printed JSON to `path`
+pub fn write(path: &Path, doc: &Document) -> CargoResult<()> {
+    let json = serde_json::to_vec_pretty(doc)
+        .map_err(|e| anyhow!("Unable to write JSON file '{}': {}", path.display(), e))?;
+    output::write(path, &json)
+        .map_err(|e| anyhow!("Unable to write JSON file '{}': {}", path.display(), e))
+}
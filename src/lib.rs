@@ -0,0 +1,2578 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Generates BitBake recipes for Cargo projects, the library behind
+//! `cargo bitbake` for tools that want the recipes without running it.
+
+extern crate anyhow;
+extern crate cargo;
+extern crate curl;
+extern crate flate2;
+extern crate git2;
+extern crate itertools;
+extern crate lazy_static;
+extern crate md5;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
+extern crate tar;
+extern crate toml;
+
+use anyhow::anyhow;
+use cargo::core::compiler::{CompileKind, CompileTarget, CrateType, RustcTargetData};
+use cargo::core::dependency::DepKind;
+use cargo::core::manifest::ManifestMetadata;
+use cargo::core::registry::PackageRegistry;
+use cargo::core::resolver::features::RequestedFeatures;
+use cargo::core::resolver::ResolveOpts;
+use cargo::core::source::GitReference;
+use cargo::core::{FeatureValue, Package, PackageId, PackageSet, Resolve, Workspace};
+use cargo::ops;
+use cargo::util::config::StringList;
+use cargo::util::errors::{HttpNot200, ManifestError};
+use cargo::util::{important_paths, CargoResult, CargoResultExt};
+use cargo::Config;
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::default::Default;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+pub mod checksum;
+mod depends;
+pub mod diff;
+pub mod git;
+pub mod json;
+mod license;
+pub mod output;
+mod packageconfig;
+pub mod registry;
+mod settings;
+pub mod template;
+mod update;
+pub mod vendor;
+
+#[cfg(test)]
+mod testing {
+    use std::fs;
+    use std::path::Path;
+
+    /// A directory of its own for the fixtures of one test, so tests
+    /// running in parallel or again don't see each other's files. It's
+    /// removed when dropped.
+    pub fn temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("cargo-bitbake-")
+            .tempdir()
+            .expect("Unable to create a temporary directory")
+    }
+
+    /// Writes a crate called `name` with an empty library to `dir`, adding
+    /// `extra_toml` to its Cargo.toml after the package's version
+    pub fn write_crate(dir: &Path, name: &str, extra_toml: &str) {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n{}",
+                name, extra_toml
+            ),
+        )
+        .unwrap();
+        fs::write(dir.join("src").join("lib.rs"), "").unwrap();
+    }
+}
+
+/// Represents the package we are trying to generate a recipe for
+struct PackageInfo<'cfg> {
+    cfg: &'cfg Config,
+    ws: Workspace<'cfg>,
+    /// the platform dependencies are limited to, when one was requested
+    target: Option<(RustcTargetData, CompileKind)>,
+}
+
+impl<'cfg> PackageInfo<'cfg> {
+    /// creates our package info from the config and the manifest_path,
+    /// which may not be provided, limiting dependencies to those used
+    /// on the `target` triple if given
+    fn new(
+        config: &'cfg Config,
+        manifest_path: Option<String>,
+        target: Option<&str>,
+    ) -> CargoResult<PackageInfo<'cfg>> {
+        let manifest_path = match manifest_path {
+            Some(path) => {
+                let path = config.cwd().join(path);
+                if !path.ends_with("Cargo.toml") || !path.is_file() {
+                    return Err(anyhow!(
+                        "The manifest path '{}' is not a Cargo.toml file",
+                        path.display()
+                    ));
+                }
+                path
+            }
+            None => config.cwd().to_path_buf(),
+        };
+        let root = important_paths::find_root_manifest_for_wd(&manifest_path).map_err(|_| {
+            anyhow!(
+                "No Cargo.toml found in {} or any parent; run inside a Rust project or pass --manifest-path",
+                config.cwd().display()
+            )
+        })?;
+        let ws = Workspace::new(&root, config)
+            .map_err(|e| match workspace_inheritance(&e, &root) {
+                // cargo only reports the type of the field being wrong
+                Some((manifest, field)) => e.context(format!(
+                    "{} inherits package.{} from the workspace, which cargo-bitbake doesn't support",
+                    manifest.display(),
+                    field
+                )),
+                None => e,
+            })
+            .chain_err(|| format!("Unable to load the cargo project at {}", root.display()))?;
+        PackageInfo::with_workspace(config, ws, target)
+    }
+
+    /// creates our package info for a `package` downloaded from a registry,
+    /// which is resolved on its own so no Cargo.lock is written into the
+    /// cargo cache it was extracted to
+    fn downloaded(
+        config: &'cfg Config,
+        package: Package,
+        target: Option<&str>,
+    ) -> CargoResult<PackageInfo<'cfg>> {
+        let ws = Workspace::ephemeral(package, config, None, true)?;
+        PackageInfo::with_workspace(config, ws, target)
+    }
+
+    /// creates our package info for the workspace `ws`, limiting
+    /// dependencies to those used on the `target` triple if given
+    fn with_workspace(
+        config: &'cfg Config,
+        ws: Workspace<'cfg>,
+        target: Option<&str>,
+    ) -> CargoResult<PackageInfo<'cfg>> {
+        let target = match target {
+            Some(triple) => {
+                let kind = CompileKind::Target(CompileTarget::new(triple)?);
+                Some((RustcTargetData::new(&ws, &[kind])?, kind))
+            }
+            None => None,
+        };
+        Ok(PackageInfo {
+            cfg: config,
+            ws,
+            target,
+        })
+    }
+
+    /// provides the template set as `default_template` in the settings
+    /// file of the package or, failing that, of the workspace root
+    fn default_template(&self) -> CargoResult<Option<PathBuf>> {
+        let dir = self
+            .ws
+            .current_opt()
+            .map_or(self.ws.root(), |pkg| pkg.root());
+        let path = match settings::find(dir, self.ws.root()) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let template = settings::read(&path)?.default_template;
+        if let Some(ref template) = template {
+            self.cfg.shell().status(
+                "Template",
+                format!("{} from {}", template.display(), path.display()),
+            )?;
+        }
+        Ok(template)
+    }
+
+    /// provides the package we are working with, which is the workspace
+    /// member called `name` if given
+    fn package(&self, name: Option<&str>) -> CargoResult<&Package> {
+        let members = || self.ws.members().map(|pkg| pkg.name()).join(", ");
+        match name {
+            Some(name) => self
+                .ws
+                .members()
+                .find(|pkg| pkg.name().as_str() == name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No package '{}' in the workspace, the members are: {}",
+                        name,
+                        members()
+                    )
+                }),
+            None => self.ws.current_opt().ok_or_else(|| {
+                anyhow!(
+                    "The manifest is a virtual workspace, pick one of its members with -p: {}",
+                    members()
+                )
+            }),
+        }
+    }
+
+    /// provides the members listed in `default-members` of a virtual
+    /// workspace, which are what cargo builds at its root. There are none
+    /// when the list isn't given or the workspace has a root package.
+    fn default_members(&self) -> CargoResult<Vec<&Package>> {
+        if self.ws.current_opt().is_some() {
+            return Ok(vec![]);
+        }
+        let root = read_manifest(self.ws.root_manifest())?;
+        let declared = root
+            .get("workspace")
+            .and_then(|w| w.get("default-members"))
+            .is_some();
+        Ok(if declared {
+            self.ws.default_members().collect()
+        } else {
+            vec![]
+        })
+    }
+
+    /// provides the workspace members we can generate recipes for. Members
+    /// that aren't published are skipped unless `include_unpublished` is set
+    fn members(&self, include_unpublished: bool) -> Vec<&Package> {
+        self.ws
+            .members()
+            .filter(|pkg| match pkg.publish() {
+                Some(registries) if registries.is_empty() => include_unpublished,
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// Provides every package that any of `roots` depends on, other than
+    /// the roots themselves, as `dependencies_of` does for each of them
+    fn dependencies_of_all(
+        &self,
+        resolve: &Resolve,
+        roots: &[PackageId],
+        kinds: &[DepKind],
+    ) -> Vec<PackageId> {
+        let mut deps = roots
+            .iter()
+            .flat_map(|&root| self.dependencies_of(resolve, root, kinds))
+            .filter(|id| !roots.contains(id))
+            .collect::<Vec<_>>();
+        deps.sort();
+        deps.dedup();
+        deps
+    }
+
+    /// provides the names of the crates the workspace replaces with `[patch]`
+    fn patched(&self) -> HashSet<String> {
+        self.ws
+            .root_patch()
+            .values()
+            .flat_map(|deps| deps.iter())
+            .map(|dep| dep.package_name().to_string())
+            .collect()
+    }
+
+    /// Generates a package registry by using the Cargo.lock or
+    /// creating one as necessary
+    fn registry(&self) -> CargoResult<PackageRegistry<'cfg>> {
+        let mut registry = PackageRegistry::new(self.cfg)?;
+        let sources = self
+            .ws
+            .members()
+            .map(|pkg| pkg.package_id().source_id())
+            .collect::<Vec<_>>();
+        registry.add_sources(sources)?;
+        Ok(registry)
+    }
+
+    /// Resolve the packages necessary for the workspace with the features in `opts`
+    fn resolve(&self, opts: &ResolveOpts) -> CargoResult<(PackageSet<'cfg>, Resolve)> {
+        // build up our registry
+        let mut registry = self.registry()?;
+
+        // resolve our dependencies, which fails if Cargo.lock needs
+        // to change and that isn't allowed
+        let (_, locked) = ops::resolve_ws(&self.ws)?;
+
+        // resolve with the requested features so we ensure we get all of the depends downloaded
+        let resolve = ops::resolve_with_previous(
+            &mut registry,
+            &self.ws,
+            /* features to resolve */
+            opts,
+            /* previous */
+            Some(&locked),
+            /* don't avoid any */
+            None,
+            /* specs */
+            &[],
+            /* warn? */
+            true,
+        )?;
+
+        // make sure the full resolve didn't need anything that isn't
+        // in Cargo.lock since it can't be updated when locked
+        if !self.cfg.lock_update_allowed() {
+            let locked = locked.iter().collect::<HashSet<_>>();
+            let missing = resolve
+                .iter()
+                .filter(|id| !locked.contains(id))
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                return Err(anyhow!(
+                    "Cargo.lock needs to be updated to include {} but --locked was passed",
+                    missing.join(", ")
+                ));
+            }
+        }
+
+        // the package set must cover everything in the full resolve so that
+        // any crate we need to inspect can be downloaded
+        let ids = resolve.iter().collect::<Vec<_>>();
+        let packages = registry.get(&ids)?;
+
+        Ok((packages, resolve))
+    }
+
+    /// Provides every package that `root` depends on, directly or
+    /// transitively, in the resolved dependency graph. Only dependencies
+    /// of the given `kinds` are followed, so packages only reached through
+    /// the others are skipped. When there is a target, dependencies that
+    /// aren't used on it are skipped.
+    fn dependencies_of(
+        &self,
+        resolve: &Resolve,
+        root: PackageId,
+        kinds: &[DepKind],
+    ) -> Vec<PackageId> {
+        // build dependencies and everything they depend on are built for the
+        // host, so track what each package is being built for
+        let root_kind = self
+            .target
+            .as_ref()
+            .map_or(CompileKind::Host, |&(_, kind)| kind);
+        let mut seen = HashSet::new();
+        let mut queue = vec![(root, root_kind)];
+        while let Some((id, kind)) = queue.pop() {
+            for (dep, edges) in resolve.deps(id) {
+                for edge in edges {
+                    if !kinds.contains(&edge.kind()) {
+                        continue;
+                    }
+                    let dep_kind = if edge.kind() == DepKind::Build {
+                        CompileKind::Host
+                    } else {
+                        kind
+                    };
+                    if let Some((ref data, _)) = self.target {
+                        if !data.dep_platform_activated(edge, dep_kind) {
+                            continue;
+                        }
+                    }
+                    if seen.insert((dep, dep_kind)) {
+                        queue.push((dep, dep_kind));
+                    }
+                }
+            }
+        }
+
+        let mut deps = seen
+            .into_iter()
+            .map(|(id, _)| id)
+            .filter(|&id| id != root)
+            .collect::<Vec<_>>();
+        deps.sort();
+        deps.dedup();
+        deps
+    }
+
+    /// packages that are part of a workspace are a sub directory from the
+    /// top level which we need to record, this provides us with that
+    /// relative directory
+    fn rel_dir(&self, package: &Package) -> CargoResult<PathBuf> {
+        // this is the top level of the workspace
+        let root = self.ws.root().to_path_buf();
+        // path where the package's Cargo.toml lives
+        let cwd = package.manifest_path().parent().ok_or_else(|| {
+            anyhow!(
+                "Could not get parent of directory '{}'",
+                package.manifest_path().display()
+            )
+        })?;
+
+        cwd.strip_prefix(&root)
+            .map(|p| p.to_path_buf())
+            .chain_err(|| anyhow!("Unable to if Cargo.toml is in a sub directory"))
+    }
+}
+
+/// The classes a recipe can inherit to build with cargo
+pub const INHERIT_CLASSES: &[&str] = &["cargo", "cargo_bin", "cargo_c"];
+
+/// What to generate recipes for and how, the library's counterpart of the
+/// command line arguments of `cargo bitbake`
+#[derive(Debug)]
+pub struct GenerateOptions {
+    /// Template files to render. When there are none the `default_template`
+    /// of the project's `.cargo-bitbake.toml` is used, falling back to the
+    /// built-in template. `-` reads a template from stdin.
+    pub templates: Vec<PathBuf>,
+    /// Extension of the recipe produced by the template read from stdin
+    pub extension: Option<String>,
+    /// Directory the recipes are meant to be written to, which their paths are in
+    pub output_dir: PathBuf,
+    /// Registry host to use in crate:// URIs instead of the one detected for each crate
+    pub registry: Option<String>,
+    /// Generate a recipe for every member of the workspace
+    pub workspace: bool,
+    /// Generate a single recipe for the whole workspace, fetching the
+    /// dependencies of every member. `pn` and `pv` name and version it.
+    /// Path dependencies inside the workspace are members too, built from
+    /// its sources rather than fetched.
+    pub merge_workspace: bool,
+    /// Workspace member to generate a recipe for
+    pub package: Option<String>,
+    /// Include workspace members with `publish = false` when using `workspace`
+    pub include_unpublished: bool,
+    /// Path to the Cargo.toml of the project to generate a recipe for
+    pub manifest_path: Option<String>,
+    /// Features to resolve dependencies with. All features are used when
+    /// neither this nor `no_default_features` are given
+    pub features: Vec<String>,
+    /// Do not activate the `default` feature
+    pub no_default_features: bool,
+    /// Replace underscores in the package name with hyphens for the recipe name
+    pub rename_underscores: bool,
+    /// Version to use for PV and the recipe file name instead of the package version
+    pub pv: Option<String>,
+    /// Branch names git dependencies can be fetched from without naming the
+    /// branch. Defaults to `master` and `main`
+    pub default_branches: Vec<String>,
+    /// Vendor the registry crates into this directory and fetch them as a
+    /// single archive instead of a crate:// entry per crate
+    pub vendor: Option<PathBuf>,
+    /// How the vendored archive is compressed
+    pub vendor_compress: vendor::Compression,
+    /// Track the head of git dependency branches with ${AUTOREV} instead
+    /// of using the commit pinned in Cargo.lock
+    pub autorev: bool,
+    /// Use the commit Cargo.lock pinned a git dependency's tag to as its
+    /// SRCREV, since a tag can be moved
+    pub pin_tags: bool,
+    /// License expression to use when the crate doesn't set one, instead of CLOSED
+    pub default_license: Option<String>,
+    /// Generate a .bbappend that only overrides the dependencies of an
+    /// existing recipe instead of a full recipe
+    pub bbappend: bool,
+    /// Recipe name (PN) to use instead of the package name
+    pub pn: Option<String>,
+    /// TOML file mapping `links` values to the recipes that provide them,
+    /// overriding the built-in mapping used for DEPENDS
+    pub depends_map: Option<PathBuf>,
+    /// Additional `name=value` to fill in `{name}` in custom templates
+    pub defines: Vec<(String, String)>,
+    /// Leave out crates only used as build dependencies
+    pub no_build_deps: bool,
+    /// Fetch the crates only used as dev dependencies, which running the
+    /// package's tests needs
+    pub include_dev_deps: bool,
+    /// Use the host of the mirror configured with source replacement in
+    /// crate:// URIs instead of the original registry
+    pub mirror_host: bool,
+    /// Checksums to provide for crates from a registry
+    pub checksum: checksum::Kind,
+    /// Only include the dependencies used when building for this target
+    /// triple instead of those for every platform
+    pub target: Option<String>,
+    /// SRC_URI of the project itself, instead of the git remote it's checked out from
+    pub src_uri: Option<String>,
+    /// SRCREV of the project itself, instead of the commit it's checked out at
+    pub src_rev: Option<String>,
+    /// Number of characters of the project revision added to PV when it isn't a tag
+    pub srcrev_length: usize,
+    /// Don't add AUTOINC and the project revision to PV when it isn't a tag
+    pub no_autoinc: bool,
+    /// Map the features of the crate to PACKAGECONFIG options
+    pub packageconfig: bool,
+    /// Resolve and render without writing anything, not even the vendored crates
+    pub dry_run: bool,
+    /// Only fetch the dependencies within this many edges of the package,
+    /// leaving the recipe incomplete
+    pub max_depth: Option<usize>,
+    /// Directory under ${WORKDIR} that git dependencies are unpacked into
+    pub git_workdir: Option<String>,
+    /// Unpack git dependencies under a directory named after the recipe so
+    /// recipes sharing a ${WORKDIR} don't collide
+    pub git_prefix_pn: bool,
+    /// Transport to fetch git repositories with, instead of the one the
+    /// scheme of each URL implies
+    pub git_protocol: Option<String>,
+    /// Class from `INHERIT_CLASSES` to inherit instead of the one detected
+    /// from what the package builds
+    pub inherit: Option<String>,
+    /// Generate the recipe for a crate published on crates.io, given as
+    /// `name` or `name@version`, instead of a local project
+    pub krate: Option<String>,
+    /// Fetch the package, which must be published, from crates.io with a
+    /// crate:// URI instead of from its git repository
+    pub root_from_registry: bool,
+    /// Append the contents of this file to every recipe generated
+    pub append_recipe: Option<PathBuf>,
+    /// Fail when a template is missing placeholders the recipe needs
+    /// instead of only warning about it
+    pub strict: bool,
+    /// Add a BBCLASSEXTEND to the recipe with these variants, such as
+    /// "native nativesdk" for build tools needed on the host
+    pub classextend: Option<String>,
+    /// Add the binaries the package installs to FILES rather than only
+    /// suggesting it in a comment
+    pub files_bins: bool,
+    /// Only look for license files in the directory of the package,
+    /// not in the directories above it up to the workspace root
+    pub no_license_search: bool,
+    /// Checksum the lines of each license in a license file that combines
+    /// several of them rather than the whole file
+    pub license_line_ranges: bool,
+    /// How the lines of SRC_URI and LIC_FILES_CHKSUM are indented and continued
+    pub layout: template::Layout,
+    /// Layout of the crate:// URIs
+    pub crate_uri_format: registry::UriFormat,
+    /// Leave the crates with these names out of the recipe, such as ones
+    /// provided by the build environment
+    pub exclude: Vec<String>,
+    /// Warn about crates the recipe fetches at several versions that aren't
+    /// semver compatible, which hints at dependencies that could be trimmed
+    pub warn_duplicate_versions: bool,
+    /// Provide every license used by the package and its dependencies,
+    /// joined with `&`, to templates as `{all_licenses}`
+    pub aggregate_licenses: bool,
+    /// Number of times to retry resolving the dependencies when downloading
+    /// them fails. Nothing is retried when offline
+    pub download_retries: u32,
+    /// Refresh the dependencies, their checksums and revisions, of this
+    /// recipe generated earlier, leaving the rest of it as it is
+    pub update: Option<PathBuf>,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> GenerateOptions {
+        GenerateOptions {
+            templates: vec![],
+            extension: None,
+            output_dir: PathBuf::new(),
+            registry: None,
+            workspace: false,
+            merge_workspace: false,
+            package: None,
+            include_unpublished: false,
+            manifest_path: None,
+            features: vec![],
+            no_default_features: false,
+            rename_underscores: false,
+            pv: None,
+            default_branches: vec![],
+            vendor: None,
+            vendor_compress: vendor::Compression::Xz,
+            autorev: false,
+            pin_tags: false,
+            default_license: None,
+            bbappend: false,
+            pn: None,
+            depends_map: None,
+            defines: vec![],
+            no_build_deps: false,
+            include_dev_deps: false,
+            mirror_host: false,
+            checksum: checksum::Kind::Sha256,
+            target: None,
+            src_uri: None,
+            src_rev: None,
+            srcrev_length: 10,
+            no_autoinc: false,
+            packageconfig: false,
+            dry_run: false,
+            max_depth: None,
+            git_workdir: None,
+            git_prefix_pn: false,
+            git_protocol: None,
+            inherit: None,
+            krate: None,
+            root_from_registry: false,
+            append_recipe: None,
+            strict: false,
+            classextend: None,
+            files_bins: false,
+            no_license_search: false,
+            license_line_ranges: false,
+            layout: template::Layout::default(),
+            crate_uri_format: registry::UriFormat::Slash,
+            exclude: vec![],
+            warn_duplicate_versions: false,
+            aggregate_licenses: false,
+            download_retries: 3,
+            update: None,
+        }
+    }
+}
+
+impl GenerateOptions {
+    /// determines if the package `id` was left out with `exclude`
+    fn excluded(&self, id: PackageId) -> bool {
+        self.exclude.iter().any(|name| name == id.name().as_str())
+    }
+
+    /// determines if the package itself is fetched from crates.io rather
+    /// than from its git repository
+    fn registry_root(&self) -> bool {
+        self.krate.is_some() || self.root_from_registry
+    }
+
+    /// the kinds of dependencies whose crates are fetched
+    fn dep_kinds(&self) -> Vec<DepKind> {
+        let mut kinds = vec![DepKind::Normal];
+        if !self.no_build_deps {
+            kinds.push(DepKind::Build);
+        }
+        if self.include_dev_deps {
+            kinds.push(DepKind::Development);
+        }
+        kinds
+    }
+}
+
+/// A recipe rendered by `generate_recipe`
+#[derive(Debug)]
+pub struct Recipe {
+    /// where the recipe should be written, in the output directory
+    pub path: PathBuf,
+    /// the rendered recipe
+    pub contents: String,
+    /// the number of crates the recipe fetches
+    pub dependencies: usize,
+}
+
+/// Everything `generate_recipe` produced, nothing of which has been written
+#[derive(Debug)]
+pub struct GeneratedRecipe {
+    /// the recipes, in the order the packages and templates were given
+    pub recipes: Vec<Recipe>,
+    /// the dependencies that went into the recipes of each package
+    pub dependencies: json::Document,
+    /// the Cargo.lock the dependencies were resolved from, which a crate
+    /// downloaded with `krate` only has if it was published with one
+    pub lockfile: Option<PathBuf>,
+}
+
+/// Generates the recipes for the project described by `options`, providing
+/// them rendered rather than writing them. The registry crates are written
+/// out when vendoring, unless it's a dry run, as that is what the recipes fetch.
+pub fn generate_recipe(options: &GenerateOptions, config: &Config) -> CargoResult<GeneratedRecipe> {
+    // Build up data about the package we are attempting to generate a recipe for
+    // a published crate is downloaded and used in place of a local project
+    let md = match options.krate {
+        Some(ref spec) => PackageInfo::downloaded(
+            config,
+            registry::download_crate(config, spec)?,
+            options.target.as_deref(),
+        )?,
+        None => PackageInfo::new(
+            config,
+            options.manifest_path.clone(),
+            options.target.as_deref(),
+        )?,
+    };
+
+    // The packages we are generating recipes for
+    let packages = if options.workspace || options.merge_workspace {
+        md.members(options.include_unpublished)
+    } else {
+        // like cargo, the root of a virtual workspace means its default members
+        let default_members = match options.package {
+            Some(_) => vec![],
+            None => md.default_members()?,
+        };
+        if default_members.is_empty() {
+            vec![md.package(options.package.as_deref())?]
+        } else {
+            default_members
+        }
+    };
+
+    // there's no single package to name a merged workspace after
+    if options.merge_workspace && (options.pn.is_none() || options.pv.is_none()) {
+        return Err(anyhow!(
+            "--merge-workspace needs --pn and --pv to name and version the recipe"
+        ));
+    }
+
+    // a recipe name can only apply to a single package
+    if options.pn.is_some() && packages.len() > 1 && !options.merge_workspace {
+        return Err(anyhow!(
+            "--pn can't be used when generating recipes for multiple packages"
+        ));
+    }
+
+    // a project can pick the template its recipes are generated from
+    // when none are given, a published crate's choice isn't ours though
+    let templates = if options.templates.is_empty() && !options.bbappend && options.krate.is_none()
+    {
+        md.default_template()?.into_iter().collect()
+    } else {
+        options.templates.clone()
+    };
+
+    // check the templates before resolving so that a bad path doesn't have to wait on that
+    let templates = &templates;
+    let stdin_templates = templates.iter().filter(|t| template::is_stdin(t)).count();
+    if stdin_templates > 1 {
+        return Err(anyhow!("Only one template can be read from stdin"));
+    }
+    if options.extension.is_some() && stdin_templates == 0 {
+        return Err(anyhow!(
+            "--extension is only used for a template read from stdin with -t -"
+        ));
+    }
+    for template in templates {
+        template::check(template, options.extension.as_deref())?;
+    }
+
+    // make sure the templates can produce a working recipe, the built-in
+    // one always does
+    let placeholders = template::placeholders(templates, &options.defines)?;
+    if !templates.is_empty() && !placeholders.missing.is_empty() {
+        let missing = placeholders
+            .missing
+            .iter()
+            .map(|t| format!("{{{}}}", t))
+            .join(", ");
+        if options.strict {
+            return Err(anyhow!(
+                "The templates are missing required placeholders: {}",
+                missing
+            ));
+        }
+        config.shell().warn(format!(
+            "The templates are missing required placeholders: {}",
+            missing
+        ))?;
+    }
+    if !placeholders.unknown.is_empty() {
+        config.shell().warn(format!(
+            "The templates have placeholders that won't be filled in: {}",
+            placeholders
+                .unknown
+                .iter()
+                .map(|t| format!("{{{}}}", t))
+                .join(", ")
+        ))?;
+    }
+
+    // site specific lines added to the end of every recipe
+    let append_recipe = match options.append_recipe {
+        Some(ref path) => Some(
+            fs::read_to_string(path)
+                .map_err(|e| anyhow!("Unable to read '{}': {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+
+    // Resolve with every feature enabled unless specific features were requested
+    let resolve_opts = if options.features.is_empty() && !options.no_default_features {
+        ResolveOpts::everything()
+    } else {
+        ResolveOpts::new(
+            /* dev deps */
+            true,
+            RequestedFeatures::from_command_line(
+                &options.features,
+                /* all features */
+                false,
+                !options.no_default_features,
+            ),
+        )
+    };
+
+    // resolving generates Cargo.lock when there isn't one
+    let lockfile = md.ws.root().join("Cargo.lock");
+    let had_lockfile = lockfile.exists();
+
+    // Resolve all dependencies (generate or use Cargo.lock as necessary),
+    // which downloads crates so retry in case of a network blip
+    let retries = if config.offline() {
+        0
+    } else {
+        options.download_retries
+    };
+    let resolve = with_retries(config, retries, || md.resolve(&resolve_opts)).map_err(|e| {
+        if config.offline() {
+            e.context("Unable to resolve dependencies offline, they must be in Cargo.lock and the cargo cache")
+        } else {
+            e
+        }
+    })?;
+
+    // a freshly generated Cargo.lock pins whatever is newest right now,
+    // which the build won't see unless it's committed
+    if !had_lockfile && lockfile.exists() {
+        config.shell().warn(format!(
+            "No Cargo.lock existed so '{}' was generated, commit it for the recipes to be reproducible",
+            lockfile.display()
+        ))?;
+    }
+
+    // crates yanked since Cargo.lock pinned them may disappear from the registry
+    match yanked(&resolve.0, &resolve.1) {
+        Ok(ref yanked) if !yanked.is_empty() => config.shell().warn(format!(
+            "Cargo.lock pins versions that have been yanked, bump them before shipping the recipe: {}",
+            yanked
+                .iter()
+                .map(|id| format!("{}-{}", id.name(), id.version()))
+                .join(", ")
+        ))?,
+        Ok(_) => {}
+        Err(e) => config
+            .shell()
+            .warn(format!("Unable to check for yanked crates: {}", e))?,
+    }
+
+    // attempt to figure out the git repo for this project unless we were told
+    // or it's a published crate, which is fetched from the registry
+    let mut project_repo = if options.registry_root() {
+        let package = md.package(options.package.as_deref())?;
+        match package.publish() {
+            Some(registries) if registries.is_empty() => {
+                return Err(anyhow!(
+                    "{} isn't published so it can't be fetched from crates.io",
+                    package.name()
+                ));
+            }
+            _ => {}
+        }
+        git::ProjectRepo {
+            uri: options.crate_uri_format.crate_uri(
+                registry::CRATES_IO_URL,
+                &package.name(),
+                &package.version().to_string(),
+            ),
+            tag: true,
+            ..Default::default()
+        }
+    } else if options.src_uri.is_some() && options.src_rev.is_some() {
+        git::ProjectRepo {
+            subdir: git::ProjectRepo::subdir(md.ws.root()),
+            ..Default::default()
+        }
+    } else {
+        match git::ProjectRepo::new(md.ws.root()) {
+            Ok(mut repo) => {
+                if let Some(ref protocol) = options.git_protocol {
+                    repo.uri = git::with_protocol(&repo.uri, protocol);
+                }
+                repo
+            }
+            Err(e) => {
+                config.shell().warn(e)?;
+                Default::default()
+            }
+        }
+    };
+
+    // an explicitly given source is treated like a tag, it won't move so
+    // PV doesn't need to carry the revision
+    if let Some(ref uri) = options.src_uri {
+        project_repo.uri = uri.clone();
+        project_repo.tag = true;
+    }
+    if let Some(ref rev) = options.src_rev {
+        project_repo.rev = rev.clone();
+        project_repo.tag = true;
+    }
+
+    // copy every registry crate the recipes need into one archive
+    if let Some(dir) = options.vendor.as_ref().filter(|_| !options.dry_run) {
+        let ids = packages
+            .iter()
+            .flat_map(|package| {
+                md.dependencies_of(&resolve.1, package.package_id(), &options.dep_kinds())
+            })
+            .filter(|&id| id.source_id().is_registry() && !options.excluded(id))
+            .collect::<BTreeSet<_>>();
+        let vendored = resolve.0.get_many(ids)?;
+        let archive = vendor::vendor(
+            md.cfg,
+            dir,
+            options.vendor_compress,
+            &vendored,
+            resolve.1.checksums(),
+        )?;
+        config.shell().status("Vendored", archive.display())?;
+    }
+
+    // each recipe along with the members whose dependencies it fetches, a
+    // merged workspace is described by its root package, or its first
+    // member when the workspace is virtual
+    let recipe_packages = if options.merge_workspace {
+        let root = packages
+            .iter()
+            .find(|pkg| pkg.manifest_path() == md.ws.root_manifest())
+            .or_else(|| packages.first())
+            .ok_or_else(|| anyhow!("The workspace has no members to merge"))?;
+        vec![(*root, packages.clone())]
+    } else {
+        packages.iter().map(|&pkg| (pkg, vec![pkg])).collect()
+    };
+
+    let mut recipes = vec![];
+    let mut dependencies = json::Document { packages: vec![] };
+    for (package, members) in recipe_packages {
+        let (package_recipes, package_deps) = package_recipes(
+            options,
+            &md,
+            &resolve,
+            &project_repo,
+            package,
+            &members,
+            templates,
+        )?;
+        for (path, mut contents) in package_recipes {
+            if let Some(ref extra) = append_recipe {
+                template::append(&mut contents, extra);
+            }
+            recipes.push(Recipe {
+                path,
+                contents,
+                dependencies: package_deps.dependencies.len(),
+            });
+        }
+        dependencies.packages.push(package_deps);
+    }
+
+    Ok(GeneratedRecipe {
+        recipes,
+        dependencies,
+        lockfile: Some(lockfile).filter(|lockfile| lockfile.exists()),
+    })
+}
+
+/// Renders the recipes for `package`, providing the path each one
+/// should be written to along with its contents and a description of
+/// the dependencies that went into them. The recipes fetch the
+/// dependencies of `members`, which is just `package` unless the whole
+/// workspace is merged into one recipe.
+fn package_recipes(
+    options: &GenerateOptions,
+    md: &PackageInfo,
+    resolve: &(PackageSet, Resolve),
+    project_repo: &git::ProjectRepo,
+    package: &Package,
+    members: &[&Package],
+    templates: &[PathBuf],
+) -> CargoResult<(Vec<(PathBuf, String)>, json::PackageDeps)> {
+    let crate_root = package
+        .manifest_path()
+        .parent()
+        .expect("Cargo.toml must have a parent");
+
+    // BitBake prefers hyphens in recipe names so optionally swap out any
+    // underscores, keeping the real crate name around for the fetcher
+    let crate_name = package.name();
+    let (name, cargo_crate_name) = if let Some(ref pn) = options.pn {
+        // an explicit recipe name only changes PN, the crate keeps its name,
+        // while a merged workspace builds every crate in it
+        if pn.as_str() == crate_name.as_str() || options.merge_workspace {
+            (pn.clone(), String::new())
+        } else {
+            (pn.clone(), format!("CARGO_CRATE_NAME = \"{}\"", crate_name))
+        }
+    } else if !crate_name.contains('_') {
+        (crate_name.to_string(), String::new())
+    } else if options.rename_underscores {
+        (
+            crate_name.replace('_', "-"),
+            format!("CARGO_CRATE_NAME = \"{}\"", crate_name),
+        )
+    } else {
+        md.cfg
+            .shell()
+            .warn("Package name contains an underscore, consider --rename-underscores")?;
+        (crate_name.to_string(), String::new())
+    };
+
+    // branches that are fetched without having to name them
+    let default_branches = if options.default_branches.is_empty() {
+        git::DEFAULT_BRANCHES
+            .iter()
+            .map(|b| b.to_string())
+            .collect()
+    } else {
+        options.default_branches.clone()
+    };
+
+    // everything the package depends on, along with the crates only needed
+    // on the build host, which can be left out, and those only needed for
+    // its tests, which are left out unless asked for
+    let roots = members
+        .iter()
+        .map(|pkg| pkg.package_id())
+        .collect::<Vec<_>>();
+    let deps = md.dependencies_of_all(&resolve.1, &roots, &options.dep_kinds());
+
+    // only the crates close to the package when sketching a recipe
+    let deps = match options.max_depth {
+        Some(max) => {
+            md.cfg.shell().warn(format!(
+                "Only including dependencies within {} of {}, the recipe is incomplete",
+                max,
+                package.name()
+            ))?;
+            within_depth(&resolve.1, &roots, &deps, max)
+        }
+        None => deps,
+    };
+
+    // crates provided some other way, such as by the Yocto environment
+    let (excluded, deps): (Vec<_>, Vec<_>) = deps.into_iter().partition(|id| options.excluded(*id));
+    if !excluded.is_empty() {
+        md.cfg.shell().warn(format!(
+            "Excluded from SRC_URI: {}",
+            excluded
+                .iter()
+                .map(|id| format!("{}-{}", id.name(), id.version()))
+                .join(", ")
+        ))?;
+    }
+
+    // show why each crate is in the recipe when asked to be very verbose
+    if md.cfg.extra_verbose() {
+        for &id in &deps {
+            let parents = roots
+                .iter()
+                .chain(deps.iter())
+                .cloned()
+                .filter(|&parent| resolve.1.deps(parent).any(|(dep, _)| dep == id))
+                .join(", ");
+            md.cfg
+                .shell()
+                .status("Resolved", format!("{} from {}", id, parents))?;
+        }
+    }
+
+    // tests run on the target too, so dev dependencies aren't build only
+    let target_kinds = options
+        .dep_kinds()
+        .into_iter()
+        .filter(|&kind| kind != DepKind::Build)
+        .collect::<Vec<_>>();
+    let target_deps = md.dependencies_of_all(&resolve.1, &roots, &target_kinds);
+    let build_only = deps
+        .iter()
+        .filter(|id| !id.source_id().is_path() && !target_deps.contains(id))
+        .map(|id| format!("{}-{}", id.name(), id.version()))
+        .collect::<Vec<_>>();
+
+    // dependencies swapped out with [patch] don't come from where the
+    // rest of the world gets them, and path ones can't be fetched at all
+    let patched = md.patched();
+    let patched_deps = deps
+        .iter()
+        .filter(|id| patched.contains(id.name().as_str()))
+        .collect::<Vec<_>>();
+    if !patched_deps.is_empty() {
+        md.cfg.shell().warn(format!(
+            "Dependencies replaced with [patch]: {}",
+            patched_deps
+                .iter()
+                .map(|id| format!("{} ({})", id.name(), id.source_id().url()))
+                .join(", ")
+        ))?;
+    }
+
+    // several major versions of a crate bloat the recipe and the build
+    if options.warn_duplicate_versions {
+        let duplicates = duplicate_versions(&deps);
+        if !duplicates.is_empty() {
+            md.cfg.shell().warn(format!(
+                "Crates at several incompatible versions: {}",
+                duplicates
+                    .iter()
+                    .map(|(name, versions)| format!("{} ({})", name, versions.join(", ")))
+                    .join(", ")
+            ))?;
+        }
+    }
+
+    // how big the dependency graph is, for reviewers of the recipe
+    let dep_count = deps.iter().filter(|id| !id.source_id().is_path()).count();
+    let max_depth = roots
+        .iter()
+        .map(|&root| dependency_depth(&resolve.1, root, &deps))
+        .max()
+        .unwrap_or(0);
+
+    // the host each registry's crates are fetched from
+    let mut hosts = HashMap::new();
+    for id in &deps {
+        let src_id = id.source_id();
+        if src_id.is_registry() && !hosts.contains_key(&src_id) {
+            let mirror = if options.mirror_host {
+                registry::mirror_host(md.cfg, src_id)?
+            } else {
+                None
+            };
+            hosts.insert(
+                src_id,
+                mirror.unwrap_or_else(|| registry::crate_host(src_id)),
+            );
+        }
+    }
+
+    // where git dependencies are unpacked to under ${WORKDIR}, which is
+    // named after the recipe when it could be shared with other recipes
+    let git_workdir = match options.git_workdir {
+        Some(ref dir) if options.git_prefix_pn => {
+            Some(format!("{}/{}", name, dir.trim_end_matches('/')))
+        }
+        None if options.git_prefix_pn => Some(name.clone()),
+        ref dir => dir.clone(),
+    };
+
+    // build the crate URIs, keeping the lines each git dependency needs
+    // together so they come out in the same order every time
+    let mut git_extras = BTreeMap::new();
+    let mut registries = BTreeSet::new();
+    let mut dependencies = vec![];
+    let mut git_revisions = BTreeMap::new();
+    let mut src_uris = deps
+        .iter()
+        .filter_map(|&pkg| {
+            // get the source info for this package
+            let src_id = pkg.source_id();
+            if roots.iter().any(|root| root.name() == pkg.name()) {
+                None
+            } else if src_id.is_registry() {
+                // this package appears in a crate registry
+                let host = hosts[&src_id].clone();
+                registries.insert(host.clone());
+                let host = options.registry.clone().unwrap_or(host);
+                let url = crate_uri(options, &host, pkg);
+                dependencies.push(json::Dependency {
+                    name: pkg.name().to_string(),
+                    version: pkg.version().to_string(),
+                    source: "registry",
+                    registry: Some(host),
+                    url: url.clone(),
+                });
+                if options.vendor.is_some() {
+                    // the crate is in the vendored archive
+                    None
+                } else {
+                    let entry = registry::src_uri(&url, &pkg.name(), &pkg.version().to_string());
+                    Some(options.layout.line(&entry))
+                }
+            } else if src_id.is_path() {
+                // we don't want to spit out path based
+                // entries since they're within the crate
+                // we are packaging
+                None
+            } else if src_id.is_git() {
+                // a branch can't be used as a revision so use the commit
+                // Cargo.lock pinned it to, or track the head of the branch,
+                // which needs the branch given to the fetcher when it isn't
+                // one of the default branches
+                let (_, _, url_rev) = git::split_source_url(src_id.url().as_str());
+                let reference = src_id.git_reference()?;
+                let (rev, branch) = git::dependency_rev(
+                    reference,
+                    src_id.precise().or(url_rev),
+                    &default_branches,
+                    options.autorev,
+                    options.pin_tags,
+                );
+
+                // look at cargo's checkout of the repository to determine if
+                // submodules need to be fetched and if the crate is in a
+                // sub directory of the repository
+                let (prefix, subpath) = match resolve.0.get_one(pkg) {
+                    Ok(dep) => git::dependency_checkout(dep.root()),
+                    Err(e) => {
+                        // nothing can be returned from here so the warning
+                        // is best effort
+                        drop(md.cfg.shell().warn(format!(
+                            "Unable to inspect git checkout of {}: {}",
+                            pkg.name(),
+                            e
+                        )));
+                        (git::GitPrefix::default(), None)
+                    }
+                };
+                let url = git::git_to_yocto_git_url(
+                    src_id.url().as_str(),
+                    Some(pkg.name().as_str()),
+                    prefix,
+                    subpath.as_deref(),
+                    branch,
+                    git_workdir.as_deref(),
+                );
+                let url = match options.git_protocol {
+                    Some(ref protocol) => git::with_protocol(&url, protocol),
+                    None => url,
+                };
+
+                let extras = git_extras
+                    .entry(pkg.name().to_string())
+                    .or_insert_with(Vec::new);
+                // save revision, noting the tag a pinned commit is for
+                if let GitReference::Tag(ref tag) = *reference {
+                    if *tag != rev {
+                        extras.push(format!("# SRCREV_{} is tag {}", pkg.name(), tag));
+                    }
+                }
+                extras.push(format!("SRCREV_{} = \"{}\"", pkg.name(), rev));
+                git_revisions.insert(pkg.name().to_string(), rev);
+                // instruct Cargo where to find this
+                extras.push(format!(
+                    "EXTRA_OECARGO_PATHS += \"${{WORKDIR}}/{}{}\"",
+                    git_workdir
+                        .as_ref()
+                        .map(|dir| format!("{}/", dir.trim_end_matches('/')))
+                        .unwrap_or_default(),
+                    pkg.name()
+                ));
+
+                dependencies.push(json::Dependency {
+                    name: pkg.name().to_string(),
+                    version: pkg.version().to_string(),
+                    source: "git",
+                    registry: None,
+                    url: url.clone(),
+                });
+                Some(options.layout.line(&url))
+            } else {
+                dependencies.push(json::Dependency {
+                    name: pkg.name().to_string(),
+                    version: pkg.version().to_string(),
+                    source: "other",
+                    registry: None,
+                    url: src_id.url().to_string(),
+                });
+                Some(options.layout.line(src_id.url().as_str()))
+            }
+        })
+        .collect::<Vec<String>>();
+
+    // sort the crate list and drop any repeated entries
+    src_uris.sort();
+    src_uris.dedup();
+
+    // one SRCREV_FORMAT covers every git dependency, in the same order
+    // as their revisions
+    let mut src_uri_extras = vec![];
+    if !git_extras.is_empty() {
+        src_uri_extras.push(git::srcrev_format(&git_extras.keys().collect::<Vec<_>>()));
+    }
+    for extras in git_extras.into_values() {
+        src_uri_extras.extend(extras);
+    }
+
+    // registry crates come from the vendored archive which unpacks into
+    // ${WORKDIR} and is where cargo is pointed to find them
+    if let Some(ref dir) = options.vendor {
+        let name = vendor::dir_name(dir)?;
+        src_uris.push(options.layout.line(&format!(
+            "file://{}.{};name={}",
+            name,
+            options.vendor_compress.extension(),
+            name
+        )));
+        src_uri_extras.push(format!(
+            "CARGO_VENDORING_DIRECTORY = \"${{WORKDIR}}/{}\"",
+            name
+        ));
+
+        // a dry run doesn't produce the archive so there's nothing to
+        // checksum, any archive left from an earlier run may be stale
+        if !options.dry_run {
+            let archive = vendor::archive_path(dir, options.vendor_compress)?;
+            if options.checksum.md5() {
+                let md5 = checksum::file_md5(&archive)
+                    .map_err(|e| anyhow!("Unable to checksum '{}': {}", archive.display(), e))?;
+                src_uri_extras.push(format!("SRC_URI[{}.md5sum] = \"{}\"", name, md5));
+            }
+            if options.checksum.sha256() {
+                let sha256 = checksum::file_sha256(&archive)
+                    .map_err(|e| anyhow!("Unable to checksum '{}': {}", archive.display(), e))?;
+                src_uri_extras.push(format!("SRC_URI[{}.sha256sum] = \"{}\"", name, sha256));
+            }
+        }
+    }
+
+    if options.registry.is_none() && registries.len() > 1 {
+        md.cfg.shell().warn(format!(
+            "Crates come from multiple registries: {}",
+            registries.iter().join(", ")
+        ))?;
+    }
+
+    // checksum every crate fetched from a registry so do_fetch can verify them
+    // with the sha256 Cargo.lock has for them, downloading any that aren't
+    // already in the cargo cache for their md5
+    let mut registry_pkgs = resolve.0.get_many(deps.iter().cloned().filter(|id| {
+        options.vendor.is_none()
+            && id.source_id().is_registry()
+            && !roots.iter().any(|root| root.name() == id.name())
+    }))?;
+    // downloads finish in any order
+    registry_pkgs.sort_by_key(|pkg| pkg.package_id());
+    for pkg in registry_pkgs {
+        let id = pkg.package_id();
+        let fetch_name = registry::fetch_name(&id.name(), &id.version().to_string());
+        if options.checksum.md5() {
+            // Cargo.lock only has the sha256 so this needs the .crate itself
+            let crate_file = checksum::crate_file(pkg).ok_or_else(|| {
+                anyhow!(
+                    "Unable to find the downloaded crate of {} to checksum with md5",
+                    id
+                )
+            })?;
+            let md5 = checksum::file_md5(&crate_file).map_err(|e| {
+                anyhow!("Unable to checksum crate '{}': {}", crate_file.display(), e)
+            })?;
+            src_uri_extras.push(format!("SRC_URI[{}.md5sum] = \"{}\"", fetch_name, md5));
+        }
+        if options.checksum.sha256() {
+            let sha256 = resolve
+                .1
+                .checksums()
+                .get(&id)
+                .cloned()
+                .flatten()
+                .ok_or_else(|| anyhow!("Cargo.lock has no checksum for {}", id))?;
+            src_uri_extras.push(format!(
+                "SRC_URI[{}.sha256sum] = \"{}\"",
+                fetch_name, sha256
+            ));
+        }
+    }
+
+    if !build_only.is_empty() {
+        src_uri_extras.push(format!(
+            "# only needed on the build host: {}",
+            build_only.join(" ")
+        ));
+    }
+
+    for id in patched_deps.iter().filter(|id| id.source_id().is_path()) {
+        src_uri_extras.push(format!(
+            "# {}-{} is patched to a local path and must be supplied another way",
+            id.name(),
+            id.version()
+        ));
+    }
+
+    // native libraries the package or its dependencies link against
+    let all_pkgs = std::iter::once(package)
+        .chain(resolve.0.get_many(deps.iter().cloned())?)
+        .collect::<Vec<_>>();
+    let mut links = vec![];
+    for pkg in &all_pkgs {
+        if let Some(lib) = pkg.manifest().links() {
+            links.push((pkg.name().to_string(), lib.to_string()));
+        }
+    }
+    links.sort();
+    let depends = depends::depends(&links, &depends::links_map(options.depends_map.as_deref())?);
+
+    // host tools the build scripts of the package or its dependencies use
+    let mut tools = vec![];
+    for pkg in &all_pkgs {
+        for (dep, edges) in resolve.1.deps(pkg.package_id()) {
+            if edges.iter().any(|e| e.kind() == DepKind::Build) {
+                tools.push((pkg.name().to_string(), dep.name().to_string()));
+            }
+        }
+    }
+    let build_tools = depends::build_tools(
+        &tools,
+        &depends::build_tools_map(options.depends_map.as_deref())?,
+    );
+    let depends = [depends, build_tools]
+        .iter()
+        .filter(|lines| !lines.is_empty())
+        .join("\n");
+
+    // every license that applies to what goes into the package
+    let all_licenses = if options.aggregate_licenses {
+        let mut exprs = vec![];
+        for pkg in &all_pkgs {
+            match pkg.manifest().metadata().license {
+                Some(ref license) => exprs.push(license.as_str()),
+                None => md.cfg.shell().warn(format!(
+                    "No package.license set for {}, it isn't in the aggregated licenses",
+                    pkg.package_id()
+                ))?,
+            }
+        }
+        license::aggregate(&exprs)
+    } else {
+        String::new()
+    };
+
+    // crate features that can be toggled with PACKAGECONFIG
+    let packageconfig = if options.packageconfig {
+        let features = package.summary().features();
+        let names = features
+            .keys()
+            .filter(|f| f.as_str() != "default")
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>();
+        let defaults = features
+            .get("default")
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|value| match *value {
+                        FeatureValue::Feature(f) => Some(f.to_string()),
+                        FeatureValue::Dep { dep_name } => Some(dep_name.to_string()),
+                        FeatureValue::DepFeature { .. } => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        packageconfig::packageconfig(&names, &defaults)
+    } else {
+        String::new()
+    };
+
+    // root package metadata
+    let metadata = package.manifest().metadata();
+
+    // package description is used as BitBake's description and the
+    // first line or sentence of it as the summary
+    let description = match metadata.description {
+        Some(ref description) => template::description(description),
+        None => {
+            md.cfg
+                .shell()
+                .warn("No package.description set in your Cargo.toml, using package.name")?;
+            package.name().to_string()
+        }
+    };
+    // the summary comes from the description as written, its lines are
+    // already joined up in the one above
+    let summary = template::summary(metadata.description.as_deref().unwrap_or(&description));
+
+    // package homepage (or source code or documentation location)
+    let homepage = match homepage(metadata) {
+        Some(("homepage", homepage)) => homepage,
+        Some((field, homepage)) => {
+            md.cfg.shell().warn(format!(
+                "No package.homepage set in your Cargo.toml, using package.{}",
+                field
+            ))?;
+            homepage
+        }
+        None => {
+            return Err(anyhow!(
+                "No package.homepage, package.repository or package.documentation set in your Cargo.toml"
+            ))
+        }
+    }
+    .trim();
+
+    // package license
+    let license = match metadata.license {
+        Some(ref license) => license.as_str(),
+        None => {
+            md.cfg
+                .shell()
+                .warn("No package.license set in your Cargo.toml, trying package.license_file")?;
+            match metadata.license_file {
+                Some(ref license_file) => license_file.as_str(),
+                None => {
+                    let default_license = options
+                        .default_license
+                        .as_deref()
+                        .unwrap_or(license::CLOSED_LICENSE);
+                    md.cfg.shell().warn(format!(
+                        "No package.license_file set in your Cargo.toml, assuming {} license",
+                        default_license
+                    ))?;
+                    default_license
+                }
+            }
+        }
+    };
+
+    // compute the relative directory into the repo our Cargo.toml is at,
+    // a merged workspace is built from its root
+    let rel_dir = if options.merge_workspace {
+        PathBuf::new()
+    } else {
+        md.rel_dir(package)?
+    };
+
+    // license files for the package, which may be shared by the workspace
+    let search_root = if options.no_license_search {
+        None
+    } else {
+        Some(md.ws.root())
+    };
+    // a license file without a license expression is identified by its text
+    let license_file = metadata
+        .license_file
+        .as_ref()
+        .filter(|_| metadata.license.is_none());
+    let (license, lic_files) = match license_file {
+        Some(file) => {
+            let (license, lic_file) =
+                license::from_file(crate_root, &rel_dir, file, options.license_line_ranges);
+            let license = match license {
+                Some(license) => license,
+                None => {
+                    md.cfg.shell().warn(format!(
+                        "Unable to identify the license in {}, set LICENSE in the recipe by hand",
+                        file
+                    ))?;
+                    license::UNKNOWN_LICENSE
+                }
+            };
+            (license.to_string(), options.layout.lines(&lic_file))
+        }
+        None => {
+            let mut lic_files = vec![];
+            let licenses = license::ids(license);
+            for lic in &licenses {
+                let entries = license::file(
+                    crate_root,
+                    &rel_dir,
+                    lic,
+                    licenses.len(),
+                    search_root,
+                    options.license_line_ranges,
+                );
+                // a file combining several licenses has a line for each
+                for line in options.layout.lines(&entries) {
+                    if !lic_files.contains(&line) {
+                        lic_files.push(line);
+                    }
+                }
+            }
+
+            for id in license::unknown_ids(license) {
+                md.cfg
+                    .shell()
+                    .warn(format!("Unknown license '{}', using it as is", id))?;
+            }
+
+            // sloppy metadata ends up with LIC_FILES_CHKSUM for the wrong text
+            for id in license::without_file(crate_root, search_root, license) {
+                md.cfg.shell().warn(format!(
+                    "{} declares the license {} but has no license file for it",
+                    package.name(),
+                    id
+                ))?;
+            }
+
+            // license data in Yocto fmt
+            (license::to_yocto(license), lic_files)
+        }
+    };
+
+    let cargo_inherit = options
+        .inherit
+        .as_deref()
+        .unwrap_or_else(|| cargo_inherit(package));
+
+    // where the project's sources are unpacked, down into the sub directory
+    // of the repo the workspace is in, published crates are unpacked by
+    // the crate fetcher along with their dependencies
+    let project_src_dir = if options.registry_root() {
+        format!(
+            "${{CARGO_VENDORING_DIRECTORY}}/{}-{}",
+            crate_name,
+            package.version()
+        )
+    } else {
+        git::project_src_dir(&project_repo.subdir)
+    };
+
+    // binaries the package builds and the one `cargo run` would pick
+    let bins = package
+        .targets()
+        .iter()
+        .filter(|t| t.is_bin())
+        .map(|t| t.name().to_string())
+        .collect::<Vec<_>>();
+    let default_bin = match package.manifest().default_run() {
+        Some(bin) => bin.to_string(),
+        None if bins.len() == 1 => bins[0].clone(),
+        None => String::new(),
+    };
+    let files = bins
+        .iter()
+        .map(|bin| {
+            format!(
+                "{}FILES_${{PN}} += \"${{bindir}}/{}\"",
+                if options.files_bins { "" } else { "# " },
+                bin
+            )
+        })
+        .join("\n");
+
+    // the oldest rustc the package builds with
+    let rust_version = rust_version(package)?.unwrap_or_default();
+
+    // if this is not a tag we need to include some data about the version in PV so that
+    // the sstate cache remains valid
+    let git_srcpv = if project_repo.tag || options.no_autoinc {
+        // its a tag, or the version is being controlled some other way,
+        // so nothing needed
+        "".into()
+    } else {
+        // we should be using ${SRCPV} here but due to a bitbake bug we cannot. see:
+        // https://github.com/meta-rust/meta-rust/issues/136
+        git::pv_append(&project_repo.rev, options.srcrev_length)
+    };
+
+    // the same git dependency shouldn't have its revision set multiple times
+    let src_uri_extras = src_uri_extras.into_iter().unique().collect::<Vec<_>>();
+
+    let package_deps = json::PackageDeps {
+        name: name.clone(),
+        version: package.version().to_string(),
+        dependencies,
+        git_revisions,
+    };
+
+    let vars = template::TemplateVars {
+        name,
+        crate_name: crate_name.to_string(),
+        cargo_crate_name,
+        version: options
+            .pv
+            .clone()
+            .unwrap_or_else(|| package.version().to_string()),
+        crate_version: package.version().to_string(),
+        summary,
+        description,
+        homepage: homepage.to_string(),
+        license,
+        lic_files: lic_files.join(""),
+        src_uri: src_uris.join(""),
+        src_uri_extras: src_uri_extras.join("\n"),
+        project_rel_dir: template::recipe_path(&rel_dir),
+        project_src_dir,
+        project_subdir: project_repo.subdir.clone(),
+        project_src_uri: project_repo.uri.clone(),
+        project_src_rev: project_repo.rev.clone(),
+        git_srcpv,
+        rust_version,
+        // cargo uses 2015 when the manifest doesn't give one
+        edition: package.manifest().edition().to_string(),
+        cargo_inherit: cargo_inherit.to_string(),
+        depends,
+        packageconfig,
+        classextend: options
+            .classextend
+            .as_ref()
+            .map(|variants| format!("BBCLASSEXTEND = \"{}\"", variants.trim()))
+            .unwrap_or_default(),
+        all_licenses,
+        bins: bins.join(" "),
+        default_bin,
+        files,
+        continuation: options.layout.continuation.clone(),
+        rustflags: rustflags(md.cfg, options.target.as_deref())?.join(" "),
+        dep_count,
+        max_depth,
+        defines: options.defines.clone(),
+    };
+
+    // Iterate over templates and apply the data to each one.
+    let mut recipes = vec![];
+    if let Some(ref path) = options.update {
+        // only the dependencies of an existing recipe are refreshed
+        let recipe = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Unable to read '{}': {}", path.display(), e))?;
+        let dependencies =
+            update::dependencies(&vars.src_uri, &vars.src_uri_extras, &vars.continuation);
+        let recipe = update::update(&recipe, &dependencies)
+            .map_err(|e| e.context(format!("Unable to update '{}'", path.display())))?;
+        recipes.push((path.clone(), recipe));
+    } else if templates.is_empty() {
+        let (recipe_file, recipe) = if options.bbappend {
+            template::render_bbappend(&vars)
+        } else {
+            template::render_default(&vars)
+        };
+        recipes.push((options.output_dir.join(recipe_file), recipe));
+    } else {
+        for template in templates {
+            let (recipe_file, recipe) =
+                template::render_template(template, options.extension.as_deref(), &vars)?;
+            recipes.push((options.output_dir.join(recipe_file), recipe));
+        }
+    }
+
+    Ok((recipes, package_deps))
+}
+
+/// Runs `f`, running it again up to `retries` more times if it fails to
+/// download something with the wait between each attempt doubling,
+/// starting at a second. Any other failure is returned right away.
+fn with_retries<T, F>(config: &Config, retries: u32, mut f: F) -> CargoResult<T>
+where
+    F: FnMut() -> CargoResult<T>,
+{
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if attempt < retries && is_network_error(&e) => {
+                let delay = Duration::from_secs(1 << attempt);
+                config.shell().warn(format!(
+                    "{:#}, retrying in {}s ({} of {})",
+                    e,
+                    delay.as_secs(),
+                    attempt + 1,
+                    retries
+                ))?;
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Determines if `err` came from fetching something over the network,
+/// which might work when tried again unlike a bad manifest or a conflict
+fn is_network_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(not_200) = cause.downcast_ref::<HttpNot200>() {
+            return not_200.code >= 500 || not_200.code == 429;
+        }
+        if let Some(git_err) = cause.downcast_ref::<git2::Error>() {
+            return matches!(
+                git_err.class(),
+                git2::ErrorClass::Net
+                    | git2::ErrorClass::Http
+                    | git2::ErrorClass::Ssh
+                    | git2::ErrorClass::Ssl
+            );
+        }
+        cause.is::<curl::Error>()
+    })
+}
+
+/// Provides the packages from a registry in `resolve` that their registry
+/// has since yanked, going by its index
+fn yanked(packages: &PackageSet, resolve: &Resolve) -> CargoResult<Vec<PackageId>> {
+    let mut sources = packages.sources_mut();
+    let mut yanked = vec![];
+    for id in resolve.iter().filter(|id| id.source_id().is_registry()) {
+        if let Some(source) = sources.get_mut(id.source_id()) {
+            if source.is_yanked(id)? {
+                yanked.push(id);
+            }
+        }
+    }
+    yanked.sort();
+    Ok(yanked)
+}
+
+/// Provides the crates in `deps` at several versions that aren't semver
+/// compatible with each other, along with all of their versions
+fn duplicate_versions(deps: &[PackageId]) -> Vec<(String, Vec<String>)> {
+    let mut versions = BTreeMap::new();
+    for id in deps.iter().filter(|id| !id.source_id().is_path()) {
+        versions
+            .entry(id.name().to_string())
+            .or_insert_with(BTreeSet::new)
+            .insert(id.version().clone());
+    }
+
+    versions
+        .into_iter()
+        .filter(|(_, versions)| {
+            // versions are compatible when they agree up to the first
+            // part that isn't zero
+            versions
+                .iter()
+                .map(|v| match (v.major, v.minor) {
+                    (0, 0) => (0, 0, v.patch),
+                    (0, minor) => (0, minor, 0),
+                    (major, _) => (major, 0, 0),
+                })
+                .collect::<BTreeSet<_>>()
+                .len()
+                > 1
+        })
+        .map(|(name, versions)| (name, versions.iter().map(|v| v.to_string()).collect()))
+        .collect()
+}
+
+/// Provides the length of the longest chain of dependencies from `root`
+/// through the `packages` it depends on, ignoring dev dependencies
+fn dependency_depth(resolve: &Resolve, root: PackageId, packages: &[PackageId]) -> usize {
+    fn depth(
+        resolve: &Resolve,
+        id: PackageId,
+        packages: &[PackageId],
+        depths: &mut HashMap<PackageId, usize>,
+    ) -> usize {
+        if let Some(&depth) = depths.get(&id) {
+            return depth;
+        }
+        // cargo doesn't allow cycles outside of dev dependencies but
+        // make sure one can't recurse forever
+        depths.insert(id, 0);
+        let max = resolve
+            .deps(id)
+            .filter(|(dep, edges)| {
+                packages.contains(dep) && edges.iter().any(|e| e.kind() != DepKind::Development)
+            })
+            .map(|(dep, _)| depth(resolve, dep, packages, depths) + 1)
+            .max()
+            .unwrap_or(0);
+        depths.insert(id, max);
+        max
+    }
+
+    depth(resolve, root, packages, &mut HashMap::new())
+}
+
+/// Provides the `packages` that are at most `max` dependency edges away from
+/// any of `roots`, only following edges through `packages`
+fn within_depth(
+    resolve: &Resolve,
+    roots: &[PackageId],
+    packages: &[PackageId],
+    max: usize,
+) -> Vec<PackageId> {
+    let mut seen = roots.iter().cloned().collect::<HashSet<_>>();
+    let mut level = roots.to_vec();
+    let mut within = vec![];
+    for _ in 0..max {
+        level = level
+            .iter()
+            .flat_map(|&id| resolve.deps(id).map(|(dep, _)| dep))
+            .filter(|dep| packages.contains(dep) && seen.insert(*dep))
+            .collect();
+        within.extend(level.iter().cloned());
+    }
+    within.sort();
+    within
+}
+
+/// Determines the class to build `package` with, C libraries are built with
+/// cargo-c and applications with cargo_bin for its install step
+fn cargo_inherit(package: &Package) -> &'static str {
+    if builds_c_library(package) {
+        "cargo_c"
+    } else if package.targets().iter().any(|target| target.is_bin()) {
+        "cargo_bin"
+    } else {
+        "cargo"
+    }
+}
+
+/// Determines if `package` builds a library for use from C, either with
+/// a `cdylib` or `staticlib` crate type or with cargo-c metadata
+fn builds_c_library(package: &Package) -> bool {
+    let c_crate_type = package
+        .targets()
+        .iter()
+        .filter(|target| target.is_lib())
+        .flat_map(|target| target.rustc_crate_types())
+        .any(|crate_type| crate_type == CrateType::Cdylib || crate_type == CrateType::Staticlib);
+    let capi = package
+        .manifest()
+        .custom_metadata()
+        .is_some_and(|metadata| metadata.get("capi").is_some());
+    c_crate_type || capi
+}
+
+/// Reads the Cargo.toml at `manifest_path` as plain TOML
+fn read_manifest(manifest_path: &Path) -> CargoResult<toml::Value> {
+    let manifest = fs::read_to_string(manifest_path)
+        .map_err(|e| anyhow!("Unable to read '{}': {}", manifest_path.display(), e))?;
+    manifest
+        .parse::<toml::Value>()
+        .map_err(|e| anyhow!("Unable to parse '{}': {}", manifest_path.display(), e))
+}
+
+/// Provides the first package field the Cargo.toml at `manifest_path`
+/// inherits from the workspace with `field.workspace = true`, which the
+/// cargo cargo-bitbake is built with predates
+fn workspace_inherited(manifest_path: &Path) -> Option<String> {
+    let manifest = read_manifest(manifest_path).ok()?;
+    let package = manifest.get("package")?.as_table()?;
+    package
+        .iter()
+        .find(|(_, value)| value.get("workspace").and_then(|v| v.as_bool()) == Some(true))
+        .map(|(field, _)| field.clone())
+}
+
+/// Provides the manifest that `err`, from loading the workspace at `root`,
+/// failed on along with the package field it inherits from the workspace,
+/// checking the workspace members cargo names in the error before the root
+fn workspace_inheritance(err: &anyhow::Error, root: &Path) -> Option<(PathBuf, String)> {
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<ManifestError>())
+        .map(|e| e.manifest_path().clone())
+        .chain(std::iter::once(root.to_path_buf()))
+        .find_map(|manifest| workspace_inherited(&manifest).map(|field| (manifest, field)))
+}
+
+/// Provides the URL to use as the homepage of a package along with the field
+/// it came from, falling back from `package.homepage` to `package.repository`
+/// and then `package.documentation`
+fn homepage(metadata: &ManifestMetadata) -> Option<(&'static str, &str)> {
+    [
+        ("homepage", &metadata.homepage),
+        ("repository", &metadata.repository),
+        ("documentation", &metadata.documentation),
+    ]
+    .iter()
+    .filter_map(|&(field, value)| value.as_ref().map(|value| (field, value.as_str())))
+    .next()
+}
+
+/// Provides the `package.rust-version` of `package`, read from its
+/// Cargo.toml since it isn't part of the manifest metadata
+fn rust_version(package: &Package) -> CargoResult<Option<String>> {
+    let manifest = read_manifest(package.manifest_path())?;
+
+    Ok(manifest
+        .get("package")
+        .and_then(|p| p.get("rust-version"))
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string()))
+}
+
+/// Provides the crate:// URI for the registry package `id`, which is always
+/// named as the registry knows it since a dependent renaming it with
+/// `package = "..."` only changes what the dependent calls it
+fn crate_uri(options: &GenerateOptions, host: &str, id: PackageId) -> String {
+    options
+        .crate_uri_format
+        .crate_uri(host, &id.name(), &id.version().to_string())
+}
+
+/// Provides the rustflags cargo is configured to build for `target` with
+/// in `.cargo/config.toml`, those for the target taking the place of the
+/// `[build]` ones as they do for cargo
+fn rustflags(config: &Config, target: Option<&str>) -> CargoResult<Vec<String>> {
+    let target_flags = match target {
+        Some(triple) => {
+            config.get::<Option<StringList>>(&format!("target.{}.rustflags", triple))?
+        }
+        None => None,
+    };
+    let flags = match target_flags {
+        Some(flags) => Some(flags),
+        None => config.get::<Option<StringList>>("build.rustflags")?,
+    };
+
+    Ok(flags
+        .map(|flags| flags.as_slice().to_vec())
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::write_crate;
+    use cargo::core::Shell;
+
+    #[test]
+    fn outside_project() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+
+        let err = PackageInfo::new(&config, None, None).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "No Cargo.toml found in {} or any parent; run inside a Rust project or pass --manifest-path",
+                dir.display()
+            )
+        );
+    }
+
+    /// a crate with a dependency on every platform and one only on windows
+    fn platform_crate(dir: &Path) {
+        for krate in &["common", "winonly"] {
+            write_crate(&dir.join(krate), krate, "");
+        }
+        write_crate(
+            dir,
+            "platform",
+            r#"
+            [workspace]
+
+            [dependencies]
+            common = { path = "common" }
+
+            [target.'cfg(windows)'.dependencies]
+            winonly = { path = "winonly" }
+            "#,
+        );
+    }
+
+    fn dependency_names(dir: &Path, target: Option<&str>) -> Vec<String> {
+        let config = Config::new(Shell::new(), dir.to_path_buf(), dir.to_path_buf());
+        let md = PackageInfo::new(&config, None, target).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let root = md.package(None).unwrap().package_id();
+        md.dependencies_of(&resolve.1, root, ALL_KINDS)
+            .iter()
+            .map(|id| id.name().to_string())
+            .collect()
+    }
+
+    const ALL_KINDS: &[DepKind] = &[DepKind::Normal, DepKind::Build, DepKind::Development];
+
+    #[test]
+    fn dev_dependencies() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        for krate in &["runtime", "testonly"] {
+            write_crate(&dir.join(krate), krate, "");
+        }
+        write_crate(
+            &dir,
+            "tested",
+            r#"
+            [workspace]
+
+            [dependencies]
+            runtime = { path = "runtime" }
+
+            [dev-dependencies]
+            testonly = { path = "testonly" }
+            "#,
+        );
+
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let root = md.package(None).unwrap().package_id();
+        let names = |options: &GenerateOptions| {
+            md.dependencies_of(&resolve.1, root, &options.dep_kinds())
+                .iter()
+                .map(|id| id.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut options = GenerateOptions::default();
+        assert_eq!(names(&options), vec!["runtime"]);
+        options.include_dev_deps = true;
+        assert_eq!(names(&options), vec!["runtime", "testonly"]);
+    }
+
+    #[test]
+    fn renamed_dependency() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        write_crate(&dir.join("real-foo"), "real-foo", "");
+        write_crate(
+            &dir,
+            "renames",
+            r#"
+            [workspace]
+
+            [dependencies]
+            foo = { path = "real-foo", package = "real-foo" }
+            "#,
+        );
+
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let root = md.package(None).unwrap().package_id();
+        let deps = md.dependencies_of(&resolve.1, root, ALL_KINDS);
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].name().as_str(), "real-foo");
+
+        // the alias must never end up in the URI or the fetch 404s
+        let uri = crate_uri(&GenerateOptions::default(), "crates.io", deps[0]);
+        assert_eq!(uri, "crate://crates.io/real-foo/0.1.0");
+    }
+
+    #[test]
+    fn inherit_class() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        write_crate(&dir.join("library"), "library", "");
+        write_crate(&dir.join("application"), "application", "");
+        let src = dir.join("application").join("src");
+        fs::rename(src.join("lib.rs"), src.join("main.rs")).unwrap();
+        write_crate(
+            &dir.join("clib"),
+            "clib",
+            "\n[lib]\ncrate-type = [\"cdylib\"]\n",
+        );
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"library\", \"application\", \"clib\"]\n",
+        )
+        .unwrap();
+
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let classes = md
+            .members(false)
+            .iter()
+            .map(|pkg| (pkg.name().to_string(), cargo_inherit(pkg)))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(classes["library"], "cargo");
+        assert_eq!(classes["application"], "cargo_bin");
+        assert_eq!(classes["clib"], "cargo_c");
+    }
+
+    #[test]
+    fn virtual_default_members() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        for krate in &["first", "second", "third"] {
+            write_crate(&dir.join(krate), krate, "");
+        }
+        let names = |manifest: &str| {
+            fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+            let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+            let md = PackageInfo::new(&config, None, None).unwrap();
+            let mut names = md
+                .default_members()
+                .unwrap()
+                .iter()
+                .map(|pkg| pkg.name().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        assert_eq!(
+            names("[workspace]\nmembers = [\"first\", \"second\", \"third\"]\ndefault-members = [\"first\", \"third\"]\n"),
+            vec!["first", "third"]
+        );
+        // without the list a member still has to be picked with -p
+        assert!(names("[workspace]\nmembers = [\"first\", \"second\", \"third\"]\n").is_empty());
+    }
+
+    #[test]
+    fn merged_workspace_dependencies() {
+        // path dependencies inside the workspace become members of it so
+        // the crates fetched for the merged recipe live outside of it
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        let ws = dir.join("workspace");
+        let crates = [
+            (dir.join("common"), ""),
+            (dir.join("only-a"), ""),
+            (ws.join("local"), "common = { path = \"../../common\" }\n"),
+            (
+                ws.join("member-a"),
+                "common = { path = \"../../common\" }\nonly-a = { path = \"../../only-a\" }\n",
+            ),
+            (
+                ws.join("member-b"),
+                "local = { path = \"../local\" }\nmember-a = { path = \"../member-a\" }\n",
+            ),
+        ];
+        for (krate, deps) in &crates {
+            write_crate(
+                krate,
+                &krate.file_name().unwrap().to_string_lossy(),
+                &format!("\n[dependencies]\n{}", deps),
+            );
+        }
+        fs::write(
+            ws.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"member-a\", \"member-b\"]\n",
+        )
+        .unwrap();
+
+        let config = Config::new(Shell::new(), ws.clone(), ws.clone());
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let members = md.members(false);
+        let roots = members
+            .iter()
+            .map(|pkg| pkg.package_id())
+            .collect::<Vec<_>>();
+        let names = md
+            .dependencies_of_all(&resolve.1, &roots, ALL_KINDS)
+            .iter()
+            .map(|id| id.name().to_string())
+            .collect::<Vec<_>>();
+        // each crate once, and neither member-a nor the in-workspace local
+        // crate are dependencies of the merged recipe, they're built from
+        // the workspace's own sources
+        assert_eq!(names, vec!["common", "only-a"]);
+        assert!(members.iter().any(|pkg| pkg.name().as_str() == "local"));
+    }
+
+    #[test]
+    fn dependency_chain_depth() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        platform_crate(&dir);
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let root = md.package(None).unwrap().package_id();
+        let deps = md.dependencies_of(&resolve.1, root, ALL_KINDS);
+        assert_eq!(dependency_depth(&resolve.1, root, &deps), 1);
+
+        // make common pull in winonly for a chain of two
+        write_crate(
+            &dir.join("common"),
+            "common",
+            "\n[dependencies]\nwinonly = { path = \"../winonly\" }\n",
+        );
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let deps = md.dependencies_of(&resolve.1, root, ALL_KINDS);
+        assert_eq!(dependency_depth(&resolve.1, root, &deps), 2);
+    }
+
+    #[test]
+    fn incompatible_versions() {
+        let src_id = cargo::core::SourceId::crates_io(&Config::default().unwrap()).unwrap();
+        let deps = [
+            ("rand", "0.7.3"),
+            ("rand", "0.8.5"),
+            ("syn", "1.0.0"),
+            ("syn", "1.0.109"),
+            ("bitflags", "1.3.2"),
+            ("bitflags", "2.4.0"),
+        ]
+        .iter()
+        .map(|(name, version)| PackageId::new(*name, *version, src_id).unwrap())
+        .collect::<Vec<_>>();
+
+        // compatible versions of syn are left alone
+        assert_eq!(
+            duplicate_versions(&deps),
+            vec![
+                (
+                    "bitflags".to_string(),
+                    vec!["1.3.2".to_string(), "2.4.0".to_string()]
+                ),
+                (
+                    "rand".to_string(),
+                    vec!["0.7.3".to_string(), "0.8.5".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn documentation_homepage() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        write_crate(
+            &dir,
+            "documented",
+            r#"
+            documentation = "https://docs.rs/documented"
+
+            [workspace]
+            "#,
+        );
+
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let package = md.package(None).unwrap();
+        let metadata = package.manifest().metadata();
+        assert_eq!(
+            homepage(metadata),
+            Some(("documentation", "https://docs.rs/documented"))
+        );
+
+        // the repository still comes before the documentation
+        let mut metadata = metadata.clone();
+        metadata.repository = Some("https://example.com".to_string());
+        assert_eq!(
+            homepage(&metadata),
+            Some(("repository", "https://example.com"))
+        );
+    }
+
+    #[test]
+    fn multi_line_summary() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        write_crate(
+            &dir,
+            "described",
+            r#"
+            description = """
+            Foo
+            Long text about foo."""
+            homepage = "https://example.com"
+            license = "MIT"
+
+            [workspace]
+            "#,
+        );
+
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let package = md.package(None).unwrap();
+        let (recipes, _) = package_recipes(
+            &GenerateOptions::default(),
+            &md,
+            &resolve,
+            &git::ProjectRepo::default(),
+            package,
+            &[package],
+            &[],
+        )
+        .unwrap();
+        let recipe = &recipes[0].1;
+        assert!(recipe.contains("SUMMARY = \"Foo\""));
+        assert!(recipe.contains("DESCRIPTION = \"Foo Long text about foo.\""));
+    }
+
+    #[test]
+    fn downloaded_without_lockfile() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        write_crate(&dir.join("common"), "common", "");
+        write_crate(
+            &dir,
+            "published",
+            "\n[dependencies]\ncommon = { path = \"common\" }\n",
+        );
+
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let source_id = cargo::core::SourceId::for_path(&dir).unwrap();
+        let (package, _) = ops::read_package(&dir.join("Cargo.toml"), source_id, &config).unwrap();
+        let md = PackageInfo::downloaded(&config, package, None).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let root = md.package(None).unwrap().package_id();
+        assert_eq!(md.dependencies_of(&resolve.1, root, ALL_KINDS).len(), 1);
+        // the cargo cache the crate was extracted to is left alone
+        assert!(!dir.join("Cargo.lock").exists());
+    }
+
+    #[test]
+    fn limited_depth() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        let crates = [
+            ("first", "second = { path = \"../second\" }\n"),
+            ("second", "third = { path = \"../third\" }\n"),
+            ("third", ""),
+        ];
+        for &(krate, deps) in &crates {
+            write_crate(
+                &dir.join(krate),
+                krate,
+                &format!("\n[dependencies]\n{}", deps),
+            );
+        }
+        write_crate(
+            &dir,
+            "chain",
+            "\n[workspace]\n\n[dependencies]\nfirst = { path = \"first\" }\nthird = { path = \"third\" }\n",
+        );
+
+        let config = Config::new(Shell::new(), dir.clone(), dir.clone());
+        let md = PackageInfo::new(&config, None, None).unwrap();
+        let resolve = md.resolve(&ResolveOpts::everything()).unwrap();
+        let root = md.package(None).unwrap().package_id();
+        let deps = md.dependencies_of(&resolve.1, root, ALL_KINDS);
+        let names = |max| {
+            within_depth(&resolve.1, &[root], &deps, max)
+                .iter()
+                .map(|id| id.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(names(0).is_empty());
+        // third is also a direct dependency
+        assert_eq!(names(1), vec!["first", "third"]);
+        assert_eq!(names(2), vec!["first", "second", "third"]);
+        assert_eq!(names(5), names(2));
+    }
+
+    #[test]
+    fn inherited_metadata() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        fs::write(
+            dir.join("Cargo.toml"),
+            r#"
+            [workspace]
+            members = ["member"]
+
+            [workspace.package]
+            license = "MIT OR Apache-2.0"
+            "#,
+        )
+        .unwrap();
+        write_crate(&dir.join("member"), "member", "license.workspace = true\n");
+
+        // the member is named whether it's loaded itself or from the root
+        let member = dir.join("member");
+        for cwd in &[member.clone(), dir.clone()] {
+            let config = Config::new(Shell::new(), cwd.clone(), cwd.clone());
+            let err = PackageInfo::new(&config, None, None).err().unwrap();
+            assert!(format!("{:#}", err).contains(&format!(
+                "{} inherits package.license from the workspace, which cargo-bitbake doesn't support",
+                member.join("Cargo.toml").display()
+            )));
+        }
+    }
+
+    #[test]
+    fn configured_rustflags() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(dir.join(".cargo")).unwrap();
+        fs::write(
+            dir.join(".cargo").join("config.toml"),
+            r#"
+            [build]
+            rustflags = ["-C", "target-feature=+crt-static"]
+
+            [target.thumbv7em-none-eabi]
+            rustflags = "-C link-arg=-Tlink.x"
+            "#,
+        )
+        .unwrap();
+        let config = Config::new(Shell::new(), dir.clone(), dir);
+
+        assert_eq!(
+            rustflags(&config, None).unwrap(),
+            vec!["-C", "target-feature=+crt-static"]
+        );
+        assert_eq!(
+            rustflags(&config, Some("x86_64-unknown-linux-gnu")).unwrap(),
+            vec!["-C", "target-feature=+crt-static"]
+        );
+        assert_eq!(
+            rustflags(&config, Some("thumbv7em-none-eabi")).unwrap(),
+            vec!["-C", "link-arg=-Tlink.x"]
+        );
+    }
+
+    #[test]
+    fn retried() {
+        let dir = std::env::temp_dir();
+        let config = Config::new(Shell::new(), dir.clone(), dir);
+        let unavailable = || HttpNot200 {
+            code: 503,
+            url: "https://crates.io".to_string(),
+        };
+
+        let mut attempts = 0;
+        let result = with_retries(&config, 1, || {
+            attempts += 1;
+            if attempts < 2 {
+                Err(anyhow::Error::new(unavailable()).context("failed to download"))
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 2);
+
+        let mut attempts = 0;
+        let result: CargoResult<()> = with_retries(&config, 0, || {
+            attempts += 1;
+            Err(unavailable().into())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        // a broken manifest won't fix itself, so it isn't retried
+        let mut attempts = 0;
+        let result: CargoResult<()> = with_retries(&config, 3, || {
+            attempts += 1;
+            Err(anyhow!("failed to parse manifest"))
+        });
+        assert_eq!(result.unwrap_err().to_string(), "failed to parse manifest");
+        assert_eq!(attempts, 1);
+    }
+
+    /// a no_std crate whose std support pulls in crates of its own
+    /// everywhere but bare metal
+    fn no_std_crate(dir: &Path) {
+        let crates = [
+            ("corelib", ""),
+            ("stdlib", "stdhelper = { path = \"../stdhelper\" }\n"),
+            ("stdhelper", ""),
+            ("baremetal", ""),
+        ];
+        for &(krate, deps) in &crates {
+            write_crate(
+                &dir.join(krate),
+                krate,
+                &format!("\n[dependencies]\n{}", deps),
+            );
+        }
+        write_crate(
+            dir,
+            "firmware",
+            r#"
+            [workspace]
+
+            [dependencies]
+            corelib = { path = "corelib" }
+
+            [target.'cfg(not(target_os = "none"))'.dependencies]
+            stdlib = { path = "stdlib" }
+
+            [target.'cfg(target_os = "none")'.dependencies]
+            baremetal = { path = "baremetal" }
+            "#,
+        );
+        fs::write(dir.join("src").join("lib.rs"), "#![no_std]\n").unwrap();
+    }
+
+    #[test]
+    fn bare_metal_dependencies() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        no_std_crate(&dir);
+        assert_eq!(
+            dependency_names(&dir, Some("thumbv7em-none-eabi")),
+            vec!["baremetal", "corelib"]
+        );
+        assert_eq!(
+            dependency_names(&dir, Some("x86_64-unknown-linux-gnu")),
+            vec!["corelib", "stdhelper", "stdlib"]
+        );
+    }
+
+    #[test]
+    fn target_dependencies() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        platform_crate(&dir);
+        assert_eq!(dependency_names(&dir, None), vec!["common", "winonly"]);
+        assert_eq!(
+            dependency_names(&dir, Some("aarch64-unknown-linux-gnu")),
+            vec!["common"]
+        );
+        assert_eq!(
+            dependency_names(&dir, Some("x86_64-pc-windows-msvc")),
+            vec!["common", "winonly"]
+        );
+    }
+}
@@ -8,25 +8,413 @@
  * except according to those terms.
  */
 
-use md5::Context;
-use std::fs::File;
-use std::io;
+use crate::checksum;
+use crate::template;
+use std::fs;
 use std::path::Path;
 
 pub const CLOSED_LICENSE: &str = "CLOSED";
 
-/// For a given file at path `license_file`, generate the MD5 sum
-fn file_md5<P: AsRef<Path>>(license_file: P) -> Result<String, io::Error> {
-    let mut file = File::open(license_file)?;
-    let mut context = Context::new();
+/// The MD5 sums of the license texts OE ships in `${COMMON_LICENSE_DIR}`
+/// for the licenses commonly used by crates
+const COMMON_LICENSES: &[(&str, &str)] = &[
+    ("Apache-2.0", "89aea4e17d99a7cacdbeed46a0096b10"),
+    ("BSD-2-Clause", "cb641bc04cda31daea161b1bc15da69f"),
+    ("BSD-3-Clause", "550794465ba0ec5312d6919e203a55f9"),
+    ("BSL-1.0", "65a7df9ad57aacf825fd252c4c33288c"),
+    ("CC0-1.0", "0ceb3372c9595f0a8067e55da801e4a1"),
+    ("ISC", "f3b90e78ea0cffb20bf5cca7947a896d"),
+    ("MIT", "0835ade698e0bcf8506ecda2f7b4f302"),
+    ("MPL-2.0", "815ca599c9df247a0c7f619bab123dad"),
+    ("Unlicense", "7246f848faa4e9c9fc0ea91122d6e680"),
+    ("Zlib", "87f239f408daca8a157858e192597633"),
+];
 
-    io::copy(&mut file, &mut context)?;
-    Ok(format!("{:x}", context.compute()))
+/// The MD5 sums of license texts crates commonly ship that differ from
+/// the copies in `COMMON_LICENSES`, such as the Rust project's Apache-2.0
+const KNOWN_LICENSE_TEXTS: &[(&str, &str)] = &[("Apache-2.0", "1836efb2eb779966696f473ee8540542")];
+
+/// How the names of the files crates ship license texts in start
+const LICENSE_FILE_PREFIXES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "UNLICENSE"];
+
+/// Lines that start the text of a license, along with the license, used to
+/// find where each license is in a file that combines several of them
+const LICENSE_HEADINGS: &[(&str, &str)] = &[
+    ("Apache License", "Apache-2.0"),
+    ("BSD 2-Clause", "BSD-2-Clause"),
+    ("BSD 3-Clause", "BSD-3-Clause"),
+    ("Boost Software License", "BSL-1.0"),
+    ("ISC License", "ISC"),
+    ("MIT License", "MIT"),
+    ("The MIT License", "MIT"),
+    ("Mozilla Public License", "MPL-2.0"),
+    ("This is free and unencumbered software", "Unlicense"),
+    ("zlib License", "Zlib"),
+];
+
+/// Used for LICENSE when the text of a license file isn't recognized
+pub const UNKNOWN_LICENSE: &str = "Unknown";
+
+/// SPDX identifiers and the names OE uses for them in LICENSE, for the
+/// licenses commonly used by crates
+const YOCTO_LICENSES: &[(&str, &str)] = &[
+    ("0BSD", "0BSD"),
+    ("AGPL-3.0", "AGPL-3.0"),
+    ("AGPL-3.0-only", "AGPL-3.0"),
+    ("Apache-2.0", "Apache-2.0"),
+    ("BSD-2-Clause", "BSD-2-Clause"),
+    ("BSD-3-Clause", "BSD-3-Clause"),
+    ("BSL-1.0", "BSL-1.0"),
+    ("CC0-1.0", "CC0-1.0"),
+    ("GPL-2.0", "GPLv2"),
+    ("GPL-2.0+", "GPLv2+"),
+    ("GPL-2.0-only", "GPLv2"),
+    ("GPL-2.0-or-later", "GPLv2+"),
+    ("GPL-3.0", "GPLv3"),
+    ("GPL-3.0+", "GPLv3+"),
+    ("GPL-3.0-only", "GPLv3"),
+    ("GPL-3.0-or-later", "GPLv3+"),
+    ("ISC", "ISC"),
+    ("LGPL-2.0", "LGPLv2"),
+    ("LGPL-2.0+", "LGPLv2+"),
+    ("LGPL-2.0-only", "LGPLv2"),
+    ("LGPL-2.0-or-later", "LGPLv2+"),
+    ("LGPL-2.1", "LGPLv2.1"),
+    ("LGPL-2.1+", "LGPLv2.1+"),
+    ("LGPL-2.1-only", "LGPLv2.1"),
+    ("LGPL-2.1-or-later", "LGPLv2.1+"),
+    ("LGPL-3.0", "LGPLv3"),
+    ("LGPL-3.0+", "LGPLv3+"),
+    ("LGPL-3.0-only", "LGPLv3"),
+    ("LGPL-3.0-or-later", "LGPLv3+"),
+    ("MIT", "MIT"),
+    ("MPL-2.0", "MPL-2.0"),
+    ("Unlicense", "Unlicense"),
+    ("Zlib", "Zlib"),
+];
+
+/// Looks up the name OE uses for the SPDX license identifier `id`
+fn yocto_name(id: &str) -> Option<&'static str> {
+    YOCTO_LICENSES
+        .iter()
+        .find(|&&(spdx, _)| spdx.eq_ignore_ascii_case(id))
+        .map(|&(_, yocto)| yocto)
+}
+
+/// Normalizes the SPDX license identifier `id` to the name OE expects
+/// in LICENSE. Identifiers without a known name are passed through.
+pub fn spdx_to_yocto(id: &str) -> &str {
+    if id == CLOSED_LICENSE {
+        return id;
+    }
+
+    yocto_name(id).unwrap_or(id)
+}
+
+/// Provides the identifiers in the license expression `expr` that
+/// don't have a known name in OE and are used as they are
+pub fn unknown_ids(expr: &str) -> Vec<String> {
+    ids(expr)
+        .into_iter()
+        .filter(|id| id != CLOSED_LICENSE && yocto_name(id).is_none())
+        .collect()
+}
+
+/// Looks up the MD5 sum of the OE provided text for `license_name`
+fn common_license_md5(license_name: &str) -> Option<&'static str> {
+    COMMON_LICENSES
+        .iter()
+        .find(|&&(name, _)| name == license_name)
+        .map(|&(_, md5sum)| md5sum)
+}
+
+/// Identifies the license of the crate at `crate_root` from the text of its
+/// `license_file` by matching its MD5 sum against well known license texts.
+/// Provides the license, if it is recognized, along with the
+/// LIC_FILES_CHKSUM entry for the file.
+/// With `line_ranges` a file combining several licenses gets an entry for
+/// each of them.
+pub fn from_file(
+    crate_root: &Path,
+    rel_dir: &Path,
+    license_file: &str,
+    line_ranges: bool,
+) -> (Option<&'static str>, String) {
+    let md5sum = checksum::file_md5(crate_root.join(license_file)).ok();
+    let license = md5sum.as_deref().and_then(identify);
+    let rel_path = template::recipe_path(&rel_dir.join(license_file));
+    let sections = if line_ranges {
+        sections(&crate_root.join(license_file))
+    } else {
+        vec![]
+    };
+    if !sections.is_empty() {
+        return (license, section_entries(&rel_path, &sections));
+    }
+
+    (
+        license,
+        format!(
+            "file://{};md5={} \\\n",
+            rel_path,
+            md5sum.as_deref().unwrap_or("generateme")
+        ),
+    )
+}
+
+/// A license within a file that combines several of them
+#[derive(Debug)]
+struct Section {
+    license: &'static str,
+    /// the first line of the license, counting from 1
+    begin: usize,
+    /// the last line of the license
+    end: usize,
+    md5sum: String,
+}
+
+/// Finds where each license is in the file at `path` when it combines the
+/// texts of several licenses, each starting with one of `LICENSE_HEADINGS`.
+/// A file with the text of a single license has no sections.
+fn sections(path: &Path) -> Vec<Section> {
+    let text = match fs::read(path) {
+        Ok(text) => text,
+        Err(_) => return vec![],
+    };
+
+    // where each line starts, bitbake checksums a range of lines along
+    // with their newlines
+    let mut offsets = vec![0];
+    offsets.extend(
+        text.iter()
+            .enumerate()
+            .filter(|&(i, &b)| b == b'\n' && i + 1 < text.len())
+            .map(|(i, _)| i + 1),
+    );
+
+    // the first heading of each license starts its section, a license
+    // repeating its own heading doesn't start another one
+    let mut starts: Vec<(usize, &'static str)> = vec![];
+    for (line, &offset) in offsets.iter().enumerate() {
+        let end = offsets.get(line + 1).cloned().unwrap_or(text.len());
+        let content = String::from_utf8_lossy(&text[offset..end]);
+        let content = content.trim();
+        let heading = LICENSE_HEADINGS
+            .iter()
+            .find(|&&(heading, _)| content.starts_with(heading));
+        if let Some(&(_, license)) = heading {
+            if !starts.iter().any(|&(_, seen)| seen == license) {
+                starts.push((line, license));
+            }
+        }
+    }
+    if starts.len() < 2 {
+        return vec![];
+    }
+
+    // anything before the first heading, such as a title, goes with it
+    starts[0].0 = 0;
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &(begin, license))| {
+            let end = starts.get(n + 1).map_or(offsets.len(), |&(next, _)| next);
+            let bytes_end = offsets.get(end).cloned().unwrap_or(text.len());
+            Section {
+                license,
+                begin: begin + 1,
+                end,
+                md5sum: checksum::md5(&text[offsets[begin]..bytes_end]),
+            }
+        })
+        .collect()
+}
+
+/// Provides the LIC_FILES_CHKSUM entries for `sections` of the file at
+/// `rel_path`, one for each with the lines it covers
+fn section_entries(rel_path: &str, sections: &[Section]) -> String {
+    sections
+        .iter()
+        .map(|section| {
+            format!(
+                "file://{};beginline={};endline={};md5={} \\\n",
+                rel_path, section.begin, section.end, section.md5sum
+            )
+        })
+        .collect()
+}
+
+/// Identifies the license whose text has the MD5 sum `md5sum`
+fn identify(md5sum: &str) -> Option<&'static str> {
+    KNOWN_LICENSE_TEXTS
+        .iter()
+        .chain(COMMON_LICENSES)
+        .find(|&&(_, known)| known == md5sum)
+        .map(|&(name, _)| name)
+}
+
+/// Provides `crate_root` and, when given, each directory above it up to
+/// `search_root`, the directories license files are looked for in
+fn search_dirs<'a>(crate_root: &'a Path, search_root: Option<&Path>) -> Vec<&'a Path> {
+    let mut dirs = vec![crate_root];
+    if let Some(root) = search_root {
+        let mut dir = crate_root;
+        while dir != root && dir.starts_with(root) {
+            match dir.parent() {
+                Some(parent) => {
+                    dir = parent;
+                    dirs.push(dir);
+                }
+                None => break,
+            }
+        }
+    }
+    dirs
+}
+
+/// Finds the licenses in the Cargo license expression `expr` that none of
+/// the license files in `crate_root`, or the directories above it up to
+/// `search_root`, are for, judging by their names or their text, such as
+/// `MIT` for a crate that only ships `LICENSE-APACHE`. A crate without any
+/// license files has nothing to compare against.
+pub fn without_file(crate_root: &Path, search_root: Option<&Path>, expr: &str) -> Vec<String> {
+    // what each license file is called after the prefix, such as `APACHE`
+    // for LICENSE-APACHE, along with the license its text is, if known
+    let files = search_dirs(crate_root, search_root)
+        .into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_uppercase();
+            let prefix = LICENSE_FILE_PREFIXES
+                .iter()
+                .find(|prefix| name.starts_with(*prefix))?;
+            let suffix = name[prefix.len()..]
+                .trim_start_matches(&['-', '_', '.'][..])
+                .trim_end_matches(".TXT")
+                .trim_end_matches(".MD")
+                .to_string();
+            let license = checksum::file_md5(entry.path())
+                .ok()
+                .and_then(|md5sum| identify(&md5sum));
+            Some((suffix, license))
+        })
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        return vec![];
+    }
+
+    ids(expr)
+        .into_iter()
+        .filter(|id| id != CLOSED_LICENSE)
+        .filter(|id| {
+            // Apache-2.0 is commonly in LICENSE-APACHE, BSD-3-Clause in LICENSE-BSD
+            let upper = id.to_uppercase();
+            let family = upper.split('-').next().unwrap_or("");
+            !files.iter().any(|(suffix, license)| match license {
+                Some(license) => license == id,
+                None => suffix.is_empty() || *suffix == upper || suffix == family,
+            })
+        })
+        .collect()
+}
+
+/// Splits a Cargo license expression into its tokens. Both SPDX expressions
+/// (`MIT OR Apache-2.0`) and the legacy slash form (`MIT/Apache-2.0`) are
+/// supported, with the slash being treated as `OR`.
+fn tokens(expr: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = vec![];
+    let expr = expr
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .replace('/', " OR ");
+    let mut words = expr.split_whitespace();
+
+    while let Some(word) = words.next() {
+        // an exception binds to the license before it, which Yocto
+        // spells as a single license name
+        if word == "WITH" {
+            if let (Some(lic), Some(exception)) = (tokens.pop(), words.next()) {
+                tokens.push(format!("{}-with-{}", lic, exception));
+            }
+            continue;
+        }
+        tokens.push(word.to_string());
+    }
+
+    tokens
+}
+
+/// Provides every distinct license identifier in a Cargo license expression
+/// in the order they first appear.
+pub fn ids(expr: &str) -> Vec<String> {
+    let mut ids: Vec<String> = vec![];
+    for token in tokens(expr) {
+        match token.as_str() {
+            "(" | ")" | "OR" | "AND" => {}
+            _ => {
+                if !ids.contains(&token) {
+                    ids.push(token);
+                }
+            }
+        }
+    }
+    ids
+}
+
+/// Converts a Cargo license expression into the format used by the
+/// Yocto LICENSE field, where `OR` is `|` and `AND` is `&` and each
+/// license is given the name OE uses for it.
+pub fn to_yocto(expr: &str) -> String {
+    let mut out = String::new();
+    for token in tokens(expr) {
+        let token = match token.as_str() {
+            "OR" => "|",
+            "AND" => "&",
+            "(" | ")" => token.as_str(),
+            id => spdx_to_yocto(id),
+        };
+
+        // keep parentheses snug against what they enclose
+        if !out.is_empty() && !out.ends_with('(') && token != ")" {
+            out.push(' ');
+        }
+        out.push_str(token);
+    }
+    out
+}
+
+/// Combines the license expressions in `exprs` into the Yocto LICENSE
+/// form of all of them applying at once, with each distinct license
+/// joined with `&` in alphabetical order
+pub fn aggregate(exprs: &[&str]) -> String {
+    let mut licenses = exprs
+        .iter()
+        .flat_map(|expr| ids(expr))
+        .map(|id| spdx_to_yocto(&id).to_string())
+        .collect::<Vec<_>>();
+    licenses.sort();
+    licenses.dedup();
+    licenses.join(" & ")
 }
 
 /// Given the top level of the crate at `crate_root`, attempt to find
 /// the license file based on the name of the license in `license_name`.
-pub fn file(crate_root: &Path, rel_dir: &Path, license_name: &str, single_license: bool) -> String {
+/// `license_count` is the number of distinct licenses the crate is under.
+/// When the crate doesn't have the file, the directories above it up to
+/// `search_root`, such as the root of its workspace, are checked too.
+/// With `line_ranges` a file combining several licenses is checked for the
+/// lines of `license_name`, or all of them when it's only for this license.
+pub fn file(
+    crate_root: &Path,
+    rel_dir: &Path,
+    license_name: &str,
+    license_count: usize,
+    search_root: Option<&Path>,
+    line_ranges: bool,
+) -> String {
     // CLOSED is a special case license (case sensitive) per
     // http://www.yoctoproject.org/docs/2.3.2/mega-manual/mega-manual.html#sdk-license-detection
     // that means this is closed source and there is no license
@@ -39,38 +427,303 @@ pub fn file(crate_root: &Path, rel_dir: &Path, license_name: &str, single_licens
     // return the right URL to it. try to handle the special
     // case license path we support as well
     let special_name = format!("LICENSE-{}", license_name);
-    let lic_path = Path::new(license_name);
-    let spec_path = Path::new(&special_name);
-    let simple_path = Path::new("LICENSE");
+    let mut candidates = vec![Path::new(license_name), Path::new(&special_name)];
+    // a LICENSE for more than one license needs to combine their texts
+    let shared = license_count > 1 && line_ranges;
+    if license_count == 1 || shared {
+        candidates.push(Path::new("LICENSE"));
+    }
 
-    let lic_abs_path = crate_root.join(lic_path);
-    let spec_abs_path = crate_root.join(spec_path);
-    let simple_abs_path = crate_root.join(simple_path);
+    // each directory to look in along with where it is relative to S,
+    // stopping at S as anything above it isn't fetched
+    let dirs = search_dirs(crate_root, search_root);
+    for (dir, rel) in dirs.into_iter().zip(rel_dir.ancestors()) {
+        for candidate in &candidates {
+            let abs_path = dir.join(candidate);
+            if abs_path.exists() {
+                let rel_path = template::recipe_path(&rel.join(candidate));
+                let sections = if line_ranges {
+                    sections(&abs_path)
+                } else {
+                    vec![]
+                };
+                let own = sections
+                    .iter()
+                    .position(|section| section.license == license_name);
+                match own {
+                    Some(i) => return section_entries(&rel_path, &sections[i..=i]),
+                    // the LICENSE of a crate with several licenses is only
+                    // used for the lines of this one
+                    None if shared && *candidate == Path::new("LICENSE") => continue,
+                    None if !sections.is_empty() => return section_entries(&rel_path, &sections),
+                    None => {}
+                }
 
-    if lic_abs_path.exists() {
-        let md5sum = file_md5(lic_abs_path).unwrap_or_else(|_| String::from("generateme"));
-        format!(
-            "file://{};md5={} \\\n",
-            rel_dir.join(lic_path).display(),
-            md5sum
-        )
-    } else if spec_abs_path.exists() {
-        // the special case
-        let md5sum = file_md5(spec_abs_path).unwrap_or_else(|_| String::from("generateme"));
-        format!(
-            "file://{};md5={} \\\n",
-            rel_dir.join(spec_path).display(),
-            md5sum
-        )
-    } else if simple_abs_path.exists() && single_license {
-        let md5sum = file_md5(simple_abs_path).unwrap_or_else(|_| String::from("generateme"));
-        format!(
-            "file://{};md5={} \\\n",
-            rel_dir.join(simple_path).display(),
-            md5sum
-        )
-    } else {
-        // fall through
-        format!("file://{};md5=generateme \\\n", license_name)
+                let md5sum =
+                    checksum::file_md5(abs_path).unwrap_or_else(|_| String::from("generateme"));
+                return format!("file://{};md5={} \\\n", rel_path, md5sum);
+            }
+        }
+    }
+
+    // the crate doesn't ship the license text so point at the
+    // copy of it that OE provides for well known licenses
+    if let Some(md5sum) = common_license_md5(license_name) {
+        return format!(
+            "file://${{COMMON_LICENSE_DIR}}/{};md5={} \\\n",
+            license_name, md5sum
+        );
+    }
+
+    // fall through
+    format!("file://{};md5=generateme \\\n", license_name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn legacy_slash() {
+        assert_eq!(to_yocto("MIT/Apache-2.0"), "MIT | Apache-2.0");
+        assert_eq!(ids("MIT/Apache-2.0"), vec!["MIT", "Apache-2.0"]);
+    }
+
+    #[test]
+    fn spdx_or() {
+        assert_eq!(to_yocto("MIT OR Apache-2.0"), "MIT | Apache-2.0");
+        assert_eq!(ids("MIT OR Apache-2.0"), vec!["MIT", "Apache-2.0"]);
+    }
+
+    #[test]
+    fn spdx_nested() {
+        let expr = "(MIT OR Apache-2.0) AND BSD-3-Clause";
+        assert_eq!(to_yocto(expr), "(MIT | Apache-2.0) & BSD-3-Clause");
+        assert_eq!(ids(expr), vec!["MIT", "Apache-2.0", "BSD-3-Clause"]);
+    }
+
+    #[test]
+    fn spdx_duplicates() {
+        let expr = "MIT AND (MIT OR Apache-2.0)";
+        assert_eq!(ids(expr), vec!["MIT", "Apache-2.0"]);
+    }
+
+    #[test]
+    fn spdx_with_exception() {
+        let expr = "Apache-2.0 WITH LLVM-exception";
+        assert_eq!(to_yocto(expr), "Apache-2.0-with-LLVM-exception");
+        assert_eq!(ids(expr), vec!["Apache-2.0-with-LLVM-exception"]);
+    }
+
+    #[test]
+    fn spdx_gpl() {
+        assert_eq!(spdx_to_yocto("GPL-2.0-only"), "GPLv2");
+        assert_eq!(spdx_to_yocto("GPL-3.0-or-later"), "GPLv3+");
+        assert_eq!(spdx_to_yocto("LGPL-2.1"), "LGPLv2.1");
+        assert_eq!(to_yocto("MIT OR LGPL-2.1-or-later"), "MIT | LGPLv2.1+");
+    }
+
+    #[test]
+    fn spdx_case() {
+        assert_eq!(spdx_to_yocto("mit"), "MIT");
+        assert_eq!(spdx_to_yocto("UNLICENSE"), "Unlicense");
+        assert_eq!(to_yocto("unlicense/mit"), "Unlicense | MIT");
+    }
+
+    #[test]
+    fn spdx_unknown() {
+        assert_eq!(spdx_to_yocto("Foo-1.0"), "Foo-1.0");
+        assert_eq!(spdx_to_yocto(CLOSED_LICENSE), CLOSED_LICENSE);
+        assert_eq!(unknown_ids("Foo-1.0 OR mit"), vec!["Foo-1.0"]);
+        assert!(unknown_ids(CLOSED_LICENSE).is_empty());
+    }
+
+    #[test]
+    fn file_in_crate_root() {
+        let tmp = crate::testing::temp_dir();
+        let crate_root = tmp.path().to_path_buf();
+        std::fs::write(crate_root.join("LICENSE-MIT"), "hello\n").unwrap();
+
+        assert_eq!(
+            file(&crate_root, Path::new("sub"), "MIT", 2, None, false),
+            "file://sub/LICENSE-MIT;md5=b1946ac92492d2347c6235b4d2611184 \\\n"
+        );
+    }
+
+    #[test]
+    fn file_in_workspace_root() {
+        let tmp = crate::testing::temp_dir();
+        let ws_root = tmp.path().to_path_buf();
+        let crate_root = ws_root.join("crates").join("member");
+        std::fs::create_dir_all(&crate_root).unwrap();
+        std::fs::write(ws_root.join("LICENSE-MIT"), "hello\n").unwrap();
+        let rel_dir = Path::new("crates/member");
+
+        assert_eq!(
+            file(&crate_root, rel_dir, "MIT", 2, Some(&ws_root), false),
+            "file://LICENSE-MIT;md5=b1946ac92492d2347c6235b4d2611184 \\\n"
+        );
+        // without searching upwards it isn't found
+        assert_eq!(
+            file(&crate_root, rel_dir, "MIT", 2, None, false),
+            "file://${COMMON_LICENSE_DIR}/MIT;md5=0835ade698e0bcf8506ecda2f7b4f302 \\\n"
+        );
+
+        // the crate's own copy wins over the workspace's
+        std::fs::write(crate_root.join("LICENSE-MIT"), "hello\n").unwrap();
+        assert_eq!(
+            file(&crate_root, rel_dir, "MIT", 2, Some(&ws_root), false),
+            "file://crates/member/LICENSE-MIT;md5=b1946ac92492d2347c6235b4d2611184 \\\n"
+        );
+    }
+
+    #[test]
+    fn file_common_license() {
+        let crate_root = Path::new("/nonexistent");
+        assert_eq!(
+            file(crate_root, Path::new(""), "Apache-2.0", 1, None, false),
+            "file://${COMMON_LICENSE_DIR}/Apache-2.0;md5=89aea4e17d99a7cacdbeed46a0096b10 \\\n"
+        );
+        assert_eq!(
+            file(crate_root, Path::new(""), "Foo", 1, None, false),
+            "file://Foo;md5=generateme \\\n"
+        );
+    }
+
+    #[test]
+    fn file_default_license() {
+        let tmp = crate::testing::temp_dir();
+        let crate_root = tmp.path().to_path_buf();
+        std::fs::write(crate_root.join("LICENSE"), "hello\n").unwrap();
+
+        assert_eq!(
+            file(&crate_root, Path::new(""), "Proprietary", 1, None, false),
+            "file://LICENSE;md5=b1946ac92492d2347c6235b4d2611184 \\\n"
+        );
+        assert_eq!(
+            file(&crate_root, Path::new(""), CLOSED_LICENSE, 1, None, false),
+            ""
+        );
+    }
+
+    #[test]
+    fn combined_license_file() {
+        let tmp = crate::testing::temp_dir();
+        let crate_root = tmp.path().to_path_buf();
+        let mit = "Licensed under either of\n\nMIT License\n\nPermission is hereby granted\n\n";
+        let apache = "                Apache License\n          Version 2.0, January 2004\n";
+        std::fs::write(crate_root.join("LICENSE"), format!("{}{}", mit, apache)).unwrap();
+
+        // without line ranges it isn't used for either license
+        assert_eq!(
+            file(&crate_root, Path::new(""), "MIT", 2, None, false),
+            "file://${COMMON_LICENSE_DIR}/MIT;md5=0835ade698e0bcf8506ecda2f7b4f302 \\\n"
+        );
+
+        assert_eq!(
+            file(&crate_root, Path::new(""), "MIT", 2, None, true),
+            format!(
+                "file://LICENSE;beginline=1;endline=6;md5={} \\\n",
+                checksum::md5(mit.as_bytes())
+            )
+        );
+        assert_eq!(
+            file(&crate_root, Path::new(""), "Apache-2.0", 2, None, true),
+            format!(
+                "file://LICENSE;beginline=7;endline=8;md5={} \\\n",
+                checksum::md5(apache.as_bytes())
+            )
+        );
+
+        // given as the license file, every license in it gets an entry
+        let (_, lic_file) = from_file(&crate_root, Path::new(""), "LICENSE", true);
+        assert_eq!(lic_file.lines().count(), 2);
+
+        // a single license repeating its heading isn't split up
+        std::fs::write(crate_root.join("LICENSE"), format!("{}{}", apache, apache)).unwrap();
+        assert_eq!(
+            file(&crate_root, Path::new(""), "Apache-2.0", 1, None, true),
+            format!(
+                "file://LICENSE;md5={} \\\n",
+                checksum::md5(format!("{}{}", apache, apache).as_bytes())
+            )
+        );
+    }
+
+    #[test]
+    fn license_from_file() {
+        let tmp = crate::testing::temp_dir();
+        let crate_root = tmp.path().to_path_buf();
+        std::fs::write(crate_root.join("COPYING"), "hello\n").unwrap();
+
+        let (license, lic_file) = from_file(&crate_root, Path::new("sub"), "COPYING", false);
+        assert_eq!(license, None);
+        assert_eq!(
+            lic_file,
+            "file://sub/COPYING;md5=b1946ac92492d2347c6235b4d2611184 \\\n"
+        );
+
+        // cargo-bitbake's own copy of the Apache license
+        let (license, _) = from_file(
+            Path::new(env!("CARGO_MANIFEST_DIR")),
+            Path::new(""),
+            "LICENSE-APACHE",
+            false,
+        );
+        assert_eq!(license, Some("Apache-2.0"));
+
+        let (license, lic_file) = from_file(&crate_root, Path::new(""), "missing", false);
+        assert_eq!(license, None);
+        assert_eq!(lic_file, "file://missing;md5=generateme \\\n");
+    }
+
+    #[test]
+    fn license_without_file() {
+        let tmp = crate::testing::temp_dir();
+        let crate_root = tmp.path().to_path_buf();
+        std::fs::write(crate_root.join("LICENSE-APACHE"), "hello\n").unwrap();
+
+        assert_eq!(without_file(&crate_root, None, "MIT"), vec!["MIT"]);
+        assert!(without_file(&crate_root, None, "Apache-2.0").is_empty());
+        assert_eq!(
+            without_file(&crate_root, None, "MIT OR Apache-2.0"),
+            vec!["MIT"]
+        );
+
+        // a member can rely on the license files of its workspace
+        let member = crate_root.join("member");
+        std::fs::create_dir_all(&member).unwrap();
+        std::fs::write(member.join("LICENSE-MIT"), "hello\n").unwrap();
+        assert_eq!(
+            without_file(&member, None, "MIT OR Apache-2.0"),
+            vec!["Apache-2.0"]
+        );
+        assert!(without_file(&member, Some(&crate_root), "MIT OR Apache-2.0").is_empty());
+
+        std::fs::write(crate_root.join("LICENSE-MIT.txt"), "hello\n").unwrap();
+        assert!(without_file(&crate_root, None, "MIT OR Apache-2.0").is_empty());
+
+        // nothing to compare against without any license files
+        assert!(without_file(Path::new("/nonexistent"), None, "MIT").is_empty());
+    }
+
+    #[test]
+    fn license_without_file_by_text() {
+        // cargo-bitbake's own copy is recognized as Apache-2.0
+        let crate_root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert!(without_file(crate_root, None, "Apache-2.0").is_empty());
+    }
+
+    #[test]
+    fn aggregated() {
+        let exprs = ["MIT OR Apache-2.0", "MIT", "Unlicense/MIT", "GPL-2.0-only"];
+        assert_eq!(aggregate(&exprs), "Apache-2.0 & GPLv2 & MIT & Unlicense");
+        assert_eq!(aggregate(&[]), "");
+    }
+
+    #[test]
+    fn single() {
+        assert_eq!(to_yocto("MIT"), "MIT");
+        assert_eq!(ids("MIT"), vec!["MIT"]);
     }
 }
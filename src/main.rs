@@ -10,133 +10,26 @@
 
 extern crate anyhow;
 extern crate cargo;
-extern crate git2;
-extern crate itertools;
-extern crate lazy_static;
-extern crate md5;
-extern crate regex;
+extern crate cargo_bitbake;
 extern crate structopt;
 
 use anyhow::anyhow;
-use cargo::core::registry::PackageRegistry;
-use cargo::core::resolver::ResolveOpts;
-use cargo::core::source::GitReference;
-use cargo::core::{Package, PackageSet, Resolve, Workspace};
-use cargo::ops;
-use cargo::util::{important_paths, CargoResult, CargoResultExt};
+use cargo::core::Verbosity;
 use cargo::{CliResult, Config};
-use itertools::Itertools;
-use std::default::Default;
+use cargo_bitbake::{diff, generate_recipe, json, output, template, GenerateOptions};
 use std::env;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::fs;
+use std::io::{self, Write};
 use std::path::PathBuf;
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
-mod git;
-mod license;
-
-/// Create a template string by replacing occurrances of name with value.
-/// We aren't worried about performance, so the copy of the string in replace and then
-/// replacing the original is fine. Could also chain the replacements together.
-macro_rules! template {
-    ($template:expr, $($name:ident = $value:expr),*, $(,)*) => {
-        $(
-            let val = $value.to_string();
-            let name = concat!("{", stringify!($name), "}");
-            let temp = $template.replace(&name, &val);
-            *$template = temp;
-        )*
-    }
-}
-
-const CRATES_IO_URL: &str = "crates.io";
-
-/// Represents the package we are trying to generate a recipe for
-struct PackageInfo<'cfg> {
-    cfg: &'cfg Config,
-    current_manifest: PathBuf,
-    ws: Workspace<'cfg>,
-}
-
-impl<'cfg> PackageInfo<'cfg> {
-    /// creates our package info from the config and the manifest_path,
-    /// which may not be provided
-    fn new(config: &Config, manifest_path: Option<String>) -> CargoResult<PackageInfo> {
-        let manifest_path = manifest_path
-            .map(PathBuf::from)
-            .unwrap_or_else(|| config.cwd().to_path_buf());
-        let root = important_paths::find_root_manifest_for_wd(&manifest_path)?;
-        let ws = Workspace::new(&root, config)?;
-        Ok(PackageInfo {
-            cfg: config,
-            current_manifest: root,
-            ws,
-        })
-    }
-
-    /// provides the current package we are working with
-    fn package(&self) -> CargoResult<&Package> {
-        self.ws.current()
-    }
-
-    /// Generates a package registry by using the Cargo.lock or
-    /// creating one as necessary
-    fn registry(&self) -> CargoResult<PackageRegistry<'cfg>> {
-        let mut registry = PackageRegistry::new(self.cfg)?;
-        let package = self.package()?;
-        registry.add_sources(vec![package.package_id().source_id()])?;
-        Ok(registry)
-    }
-
-    /// Resolve the packages necessary for the workspace
-    fn resolve(&self) -> CargoResult<(PackageSet<'cfg>, Resolve)> {
-        // build up our registry
-        let mut registry = self.registry()?;
-
-        // resolve our dependencies
-        let (packages, resolve) = ops::resolve_ws(&self.ws)?;
-
-        // resolve with all features set so we ensure we get all of the depends downloaded
-        let resolve = ops::resolve_with_previous(
-            &mut registry,
-            &self.ws,
-            /* resolve it all */
-            &ResolveOpts::everything(),
-            /* previous */
-            Some(&resolve),
-            /* don't avoid any */
-            None,
-            /* specs */
-            &[],
-            /* warn? */
-            true,
-        )?;
-
-        Ok((packages, resolve))
-    }
-
-    /// packages that are part of a workspace are a sub directory from the
-    /// top level which we need to record, this provides us with that
-    /// relative directory
-    fn rel_dir(&self) -> CargoResult<PathBuf> {
-        // this is the top level of the workspace
-        let root = self.ws.root().to_path_buf();
-        // path where our current package's Cargo.toml lives
-        let cwd = self.current_manifest.parent().ok_or_else(|| {
-            anyhow!(
-                "Could not get parent of directory '{}'",
-                self.current_manifest.display()
-            )
-        })?;
+/// environment variable holding the templates to use when none are given with -t
+const TEMPLATES_ENV: &str = "CARGO_BITBAKE_TEMPLATES";
 
-        Ok(cwd
-            .strip_prefix(&root)
-            .map(|p| p.to_path_buf())
-            .chain_err(|| anyhow!("Unable to if Cargo.toml is in a sub directory"))?)
-    }
-}
+/// environment variable holding the registry host to use when none is given
+/// with --registry
+const REGISTRY_HOST_ENV: &str = "CARGO_BITBAKE_REGISTRY_HOST";
 
 #[derive(StructOpt, Debug)]
 struct Args {
@@ -148,11 +41,402 @@ struct Args {
     #[structopt(short = "v", parse(from_occurrences))]
     verbose: usize,
 
-    /// Template files to use. Defaults to the `bitbake.template` file if not provided.
+    /// Template files to use. Defaults to the colon separated paths in
+    /// CARGO_BITBAKE_TEMPLATES, then the `default_template` of the project's
+    /// .cargo-bitbake.toml, or the `bitbake.template` file if not provided.
+    /// `-` reads a template from stdin.
     #[structopt(short = "t", parse(from_os_str))]
     templates: Option<Vec<PathBuf>>,
+
+    /// Extension of the recipe produced by the template read from stdin
+    #[structopt(long = "extension")]
+    extension: Option<String>,
+
+    /// Directory of `*.template` files to use in addition to any given with -t
+    #[structopt(long = "template-dir", parse(from_os_str))]
+    template_dir: Option<PathBuf>,
+
+    /// Directory to write the recipes to. Created if it does not exist.
+    #[structopt(short = "o", long = "output-dir", parse(from_os_str))]
+    output_dir: Option<PathBuf>,
+
+    /// Registry host to use in crate:// URIs instead of the one detected for
+    /// each crate, overrides CARGO_BITBAKE_REGISTRY_HOST
+    #[structopt(long = "registry")]
+    registry: Option<String>,
+
+    /// Print the recipes to stdout instead of writing them to files
+    #[structopt(long = "stdout")]
+    stdout: bool,
+
+    /// Generate a recipe for every member of the workspace
+    #[structopt(long = "workspace")]
+    workspace: bool,
+
+    /// Generate a single recipe for the whole workspace, fetching the
+    /// dependencies of every member. Needs --pn and --pv
+    #[structopt(
+        long = "merge-workspace",
+        conflicts_with_all = &["workspace", "package"]
+    )]
+    merge_workspace: bool,
+
+    /// Workspace member to generate a recipe for
+    #[structopt(short = "p", long = "package", conflicts_with = "workspace")]
+    package: Option<String>,
+
+    /// Include workspace members with `publish = false` when using --workspace
+    #[structopt(long = "include-unpublished")]
+    include_unpublished: bool,
+
+    /// Path to the Cargo.toml of the project to generate a recipe for
+    #[structopt(long = "manifest-path")]
+    manifest_path: Option<String>,
+
+    /// Features to resolve dependencies with. All features are used when
+    /// neither this nor --no-default-features are given
+    #[structopt(long = "features")]
+    features: Vec<String>,
+
+    /// Do not activate the `default` feature
+    #[structopt(long = "no-default-features")]
+    no_default_features: bool,
+
+    /// Replace underscores in the package name with hyphens for the recipe name
+    #[structopt(long = "rename-underscores")]
+    rename_underscores: bool,
+
+    /// Version to use for PV and the recipe file name instead of the package version
+    #[structopt(long = "pv")]
+    pv: Option<String>,
+
+    /// Branch names git dependencies can be fetched from without naming the
+    /// branch. Defaults to `master` and `main`
+    #[structopt(long = "default-branch")]
+    default_branches: Vec<String>,
+
+    /// Require Cargo.lock is up to date
+    #[structopt(long = "locked")]
+    locked: bool,
+
+    /// Require Cargo.lock and cache are up to date
+    #[structopt(long = "frozen")]
+    frozen: bool,
+
+    /// Run without accessing the network
+    #[structopt(long = "offline")]
+    offline: bool,
+
+    /// Write a JSON description of the resolved dependencies to this path
+    #[structopt(long = "emit-json", parse(from_os_str))]
+    emit_json: Option<PathBuf>,
+
+    /// Write the Cargo.lock the recipes were generated from next to them
+    #[structopt(
+        long = "emit-lockfile",
+        conflicts_with_all = &["stdout", "dry_run", "check"]
+    )]
+    emit_lockfile: bool,
+
+    /// Vendor the registry crates into this directory and fetch them as a
+    /// single archive instead of a crate:// entry per crate
+    #[structopt(long = "vendor", parse(from_os_str))]
+    vendor: Option<PathBuf>,
+
+    /// Compression of the vendored archive, for fetchers that only support
+    /// some of them
+    #[structopt(
+        long = "vendor-compress",
+        default_value = "xz",
+        possible_values = cargo_bitbake::vendor::Compression::VARIANTS
+    )]
+    vendor_compress: cargo_bitbake::vendor::Compression,
+
+    /// Track the head of git dependency branches with ${AUTOREV} instead
+    /// of using the commit pinned in Cargo.lock
+    #[structopt(long = "autorev")]
+    autorev: bool,
+
+    /// Use the commit Cargo.lock pinned a git dependency's tag to as its
+    /// SRCREV, keeping the tag as a comment, since a tag can be moved
+    #[structopt(long = "pin-tags")]
+    pin_tags: bool,
+
+    /// License expression to use when the crate doesn't set one, instead of CLOSED
+    #[structopt(long = "default-license")]
+    default_license: Option<String>,
+
+    /// Generate a .bbappend that only overrides the dependencies of an
+    /// existing recipe instead of a full recipe
+    #[structopt(long = "bbappend")]
+    bbappend: bool,
+
+    /// Recipe name (PN) to use instead of the package name
+    #[structopt(long = "pn")]
+    pn: Option<String>,
+
+    /// TOML file mapping `links` values to the recipes that provide them,
+    /// overriding the built-in mapping used for DEPENDS
+    #[structopt(long = "depends-map", parse(from_os_str))]
+    depends_map: Option<PathBuf>,
+
+    /// Additional `name=value` to fill in `{name}` in custom templates
+    #[structopt(long = "define", parse(try_from_str = cargo_bitbake::template::parse_define))]
+    defines: Vec<(String, String)>,
+
+    /// Leave out crates only used as build dependencies
+    #[structopt(long = "no-build-deps")]
+    no_build_deps: bool,
+
+    /// Fetch the crates only used as dev dependencies, for running the
+    /// package's tests
+    #[structopt(long = "include-dev-deps")]
+    include_dev_deps: bool,
+
+    /// Use the host of the mirror configured with source replacement in
+    /// crate:// URIs instead of the original registry
+    #[structopt(long = "mirror-host")]
+    mirror_host: bool,
+
+    /// Checksums to provide for crates from a registry
+    #[structopt(
+        long = "checksum",
+        default_value = "sha256",
+        possible_values = cargo_bitbake::checksum::Kind::VARIANTS
+    )]
+    checksum: cargo_bitbake::checksum::Kind,
+
+    /// Only include the dependencies used when building for this target
+    /// triple instead of those for every platform
+    #[structopt(long = "target")]
+    target: Option<String>,
+
+    /// SRC_URI of the project itself, instead of the git remote it's checked out from
+    #[structopt(long = "src-uri")]
+    src_uri: Option<String>,
+
+    /// SRCREV of the project itself, instead of the commit it's checked out at
+    #[structopt(long = "src-rev")]
+    src_rev: Option<String>,
+
+    /// Number of characters of the project revision added to PV when it isn't a tag
+    #[structopt(long = "srcrev-length", default_value = "10")]
+    srcrev_length: usize,
+
+    /// Don't add AUTOINC and the project revision to PV when it isn't a tag
+    #[structopt(long = "no-autoinc")]
+    no_autoinc: bool,
+
+    /// Map the features of the crate to PACKAGECONFIG options
+    #[structopt(long = "packageconfig")]
+    packageconfig: bool,
+
+    /// Resolve everything and report the recipes that would be written
+    /// without writing anything
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+
+    /// Only fetch the dependencies within this many edges of the package,
+    /// to sketch a recipe that is incomplete
+    #[structopt(long = "max-depth")]
+    max_depth: Option<usize>,
+
+    /// Directory under ${WORKDIR} that git dependencies are unpacked into
+    #[structopt(long = "git-workdir")]
+    git_workdir: Option<String>,
+
+    /// Unpack git dependencies into ${WORKDIR}/<recipe name> so recipes
+    /// sharing a ${WORKDIR} don't unpack them into the same place
+    #[structopt(long = "git-prefix-pn")]
+    git_prefix_pn: bool,
+
+    /// Transport to fetch git repositories with, such as ssh in CI, instead
+    /// of the one the scheme of each URL implies
+    #[structopt(long = "git-protocol", possible_values = cargo_bitbake::git::PROTOCOLS)]
+    git_protocol: Option<String>,
+
+    /// Class to inherit instead of cargo_c for C libraries, cargo_bin for
+    /// packages with binaries and cargo otherwise
+    #[structopt(long = "inherit", possible_values = cargo_bitbake::INHERIT_CLASSES)]
+    inherit: Option<String>,
+
+    /// Generate the recipe for a crate published on crates.io, given as
+    /// `name` or `name@version`, instead of a local project
+    #[structopt(
+        long = "crate",
+        conflicts_with_all = &["manifest_path", "workspace", "merge_workspace", "package"]
+    )]
+    krate: Option<String>,
+
+    /// Fetch the package, which must be published, from crates.io with a
+    /// crate:// URI instead of from its git repository
+    #[structopt(
+        long = "root-from-registry",
+        conflicts_with_all = &["workspace", "merge_workspace", "src_uri", "src_rev"]
+    )]
+    root_from_registry: bool,
+
+    /// Append the contents of this file to every recipe generated
+    #[structopt(long = "append-recipe", parse(from_os_str))]
+    append_recipe: Option<PathBuf>,
+
+    /// Fail when a template is missing placeholders the recipe needs
+    /// instead of only warning about it
+    #[structopt(long = "strict")]
+    strict: bool,
+
+    /// Add a BBCLASSEXTEND to the recipe with these variants, such as
+    /// "native nativesdk" for build tools needed on the host
+    #[structopt(long = "classextend")]
+    classextend: Option<String>,
+
+    /// Add each binary the package installs to FILES_${PN} rather than only
+    /// suggesting it in a comment
+    #[structopt(long = "files-bins")]
+    files_bins: bool,
+
+    /// Only look for license files in the directory of the package,
+    /// not in the directories above it up to the workspace root
+    #[structopt(long = "no-license-search")]
+    no_license_search: bool,
+
+    /// Checksum the lines of each license in a license file that combines
+    /// several of them, with beginline and endline, rather than the whole file
+    #[structopt(long = "license-line-ranges")]
+    license_line_ranges: bool,
+
+    /// What the lines of SRC_URI and LIC_FILES_CHKSUM are indented with,
+    /// `spaces:<width>` or `tabs:<width>`, four spaces by default
+    #[structopt(long = "indent", parse(try_from_str = cargo_bitbake::template::parse_indent))]
+    indent: Option<String>,
+
+    /// What ends the lines of SRC_URI and LIC_FILES_CHKSUM to continue
+    /// them on the next line, ` \` by default
+    #[structopt(long = "continuation")]
+    continuation: Option<String>,
+
+    /// Layout of the crate:// URIs, `crate://<host>/<name>/<version>` or
+    /// `crate://<host>/<name>-<version>` for fetchers that expect that
+    #[structopt(
+        long = "crate-uri-format",
+        default_value = "slash",
+        possible_values = cargo_bitbake::registry::UriFormat::VARIANTS
+    )]
+    crate_uri_format: cargo_bitbake::registry::UriFormat,
+
+    /// Leave the crate with this name out of the recipe, such as one
+    /// provided by the build environment. Can be repeated.
+    #[structopt(long = "exclude")]
+    exclude: Vec<String>,
+
+    /// Warn about crates fetched at several versions that aren't semver
+    /// compatible, such as rand 0.7 and 0.8
+    #[structopt(long = "warn-duplicate-versions")]
+    warn_duplicate_versions: bool,
+
+    /// Provide every license used by the package and its dependencies,
+    /// joined with `&`, to templates as `{all_licenses}`
+    #[structopt(long = "aggregate-licenses")]
+    aggregate_licenses: bool,
+
+    /// Number of times to retry resolving the dependencies when downloading
+    /// them fails. Nothing is retried with --offline
+    #[structopt(long = "download-retries", default_value = "3")]
+    download_retries: u32,
+
+    /// Refresh the dependencies, their checksums and revisions, of this
+    /// recipe generated earlier, leaving the rest of it as it is
+    #[structopt(
+        long = "update",
+        parse(from_os_str),
+        conflicts_with_all = &["workspace", "bbappend", "templates", "template_dir"]
+    )]
+    update: Option<PathBuf>,
+
+    /// Check that the recipes on disk match what would be generated,
+    /// showing the differences and failing if they don't
+    #[structopt(long = "check", conflicts_with_all = &["stdout", "dry_run"])]
+    check: bool,
+}
+
+impl Args {
+    /// the options for generating the recipes, rendering `templates`
+    fn generate_options(&self, templates: Vec<PathBuf>) -> GenerateOptions {
+        GenerateOptions {
+            templates,
+            extension: self.extension.clone(),
+            output_dir: self.output_dir.clone().unwrap_or_default(),
+            registry: self
+                .registry
+                .clone()
+                .or_else(|| env::var(REGISTRY_HOST_ENV).ok())
+                .filter(|host| !host.is_empty()),
+            workspace: self.workspace,
+            merge_workspace: self.merge_workspace,
+            package: self.package.clone(),
+            include_unpublished: self.include_unpublished,
+            manifest_path: self.manifest_path.clone(),
+            features: self.features.clone(),
+            no_default_features: self.no_default_features,
+            rename_underscores: self.rename_underscores,
+            pv: self.pv.clone(),
+            default_branches: self.default_branches.clone(),
+            vendor: self.vendor.clone(),
+            vendor_compress: self.vendor_compress,
+            autorev: self.autorev,
+            pin_tags: self.pin_tags,
+            default_license: self.default_license.clone(),
+            bbappend: self.bbappend,
+            pn: self.pn.clone(),
+            depends_map: self.depends_map.clone(),
+            defines: self.defines.clone(),
+            no_build_deps: self.no_build_deps,
+            include_dev_deps: self.include_dev_deps,
+            mirror_host: self.mirror_host,
+            checksum: self.checksum,
+            target: self.target.clone(),
+            src_uri: self.src_uri.clone(),
+            src_rev: self.src_rev.clone(),
+            srcrev_length: self.srcrev_length,
+            no_autoinc: self.no_autoinc,
+            packageconfig: self.packageconfig,
+            // checking compares against what's on disk so leaves it alone too
+            dry_run: self.dry_run || self.check,
+            max_depth: self.max_depth,
+            git_workdir: self.git_workdir.clone(),
+            git_prefix_pn: self.git_prefix_pn,
+            git_protocol: self.git_protocol.clone(),
+            inherit: self.inherit.clone(),
+            krate: self.krate.clone(),
+            root_from_registry: self.root_from_registry,
+            append_recipe: self.append_recipe.clone(),
+            strict: self.strict,
+            classextend: self.classextend.clone(),
+            files_bins: self.files_bins,
+            no_license_search: self.no_license_search,
+            license_line_ranges: self.license_line_ranges,
+            layout: self.layout(),
+            crate_uri_format: self.crate_uri_format,
+            exclude: self.exclude.clone(),
+            warn_duplicate_versions: self.warn_duplicate_versions,
+            aggregate_licenses: self.aggregate_licenses,
+            download_retries: self.download_retries,
+            update: self.update.clone(),
+        }
+    }
+
+    /// how multi-line values are laid out, the default layout for anything
+    /// not given
+    fn layout(&self) -> template::Layout {
+        let default = template::Layout::default();
+        template::Layout {
+            indent: self.indent.clone().unwrap_or(default.indent),
+            continuation: self.continuation.clone().unwrap_or(default.continuation),
+        }
+    }
 }
 
+#[derive(StructOpt, Debug)]
 #[structopt(
     name = "cargo-bitbake",
     bin_name = "cargo",
@@ -160,7 +444,6 @@ struct Args {
     about = "Generates a BitBake recipe for a given Cargo project",
     global_settings(&[AppSettings::ColoredHelp])
 )]
-#[derive(StructOpt, Debug)]
 enum Opt {
     /// Generates a BitBake recipe for a given Cargo project
     #[structopt(name = "bitbake")]
@@ -172,23 +455,19 @@ fn main() {
     let Opt::Bitbake(opt) = Opt::from_args();
     let result = real_main(opt, &mut config);
     if let Err(e) = result {
-        cargo::exit_with_error(e, &mut *config.shell());
+        cargo::exit_with_error(e, &mut config.shell());
     }
 }
 
-fn real_main(mut options: Args, config: &mut Config) -> CliResult {
-    let templates = options.templates.take();
+fn real_main(options: Args, config: &mut Config) -> CliResult {
     config.configure(
         options.verbose as u32,
         options.quiet,
         /* color */
         None,
-        /* frozen */
-        false,
-        /* locked */
-        false,
-        /* offline */
-        false,
+        options.frozen,
+        options.locked,
+        options.offline,
         /* target dir */
         &None,
         /* unstable flags */
@@ -197,244 +476,139 @@ fn real_main(mut options: Args, config: &mut Config) -> CliResult {
         &[],
     )?;
 
-    // Build up data about the package we are attempting to generate a recipe for
-    let md = PackageInfo::new(config, None)?;
-
-    // Our current package
-    let package = md.package()?;
-    let crate_root = package
-        .manifest_path()
-        .parent()
-        .expect("Cargo.toml must have a parent");
+    // the templates to render, falling back to the built-in one when there are none
+    let mut templates = match options.templates {
+        Some(ref templates) => templates.clone(),
+        // colon separated on every platform, unlike PATH
+        None => env::var_os(TEMPLATES_ENV)
+            .map(|paths| {
+                paths
+                    .to_string_lossy()
+                    .split(':')
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    if let Some(ref dir) = options.template_dir {
+        templates.extend(template::templates_in(dir)?);
+    }
 
-    if package.name().contains("_") {
-        println!("Package name contains an underscore");
+    let generated = generate_recipe(&options.generate_options(templates), config)?;
+    let recipes = generated.recipes;
+
+    // report what would be written without touching the disk
+    if options.dry_run {
+        for recipe in &recipes {
+            config.shell().status(
+                "Would write",
+                format!(
+                    "{} ({} dependencies)",
+                    recipe.path.display(),
+                    recipe.dependencies
+                ),
+            )?;
+            config.shell().verbose(|shell| {
+                writeln!(shell.err(), "{}", recipe.contents)
+                    .map_err(|e| anyhow!("Unable to write bitbake recipe to stderr: {}", e))
+            })?;
+        }
+        return Ok(());
     }
 
-    // Resolve all dependencies (generate or use Cargo.lock as necessary)
-    let resolve = md.resolve()?;
-
-    // build the crate URIs
-    let mut src_uri_extras = vec![];
-    let mut src_uris = resolve
-        .1
-        .iter()
-        .filter_map(|pkg| {
-            // get the source info for this package
-            let src_id = pkg.source_id();
-            if pkg.name() == package.name() {
-                None
-            } else if src_id.is_registry() {
-                // this package appears in a crate registry
-                Some(format!(
-                    "    crate://{}/{}/{} \\\n",
-                    CRATES_IO_URL,
-                    pkg.name(),
-                    pkg.version()
-                ))
-            } else if src_id.is_path() {
-                // we don't want to spit out path based
-                // entries since they're within the crate
-                // we are packaging
-                None
-            } else if src_id.is_git() {
-                // Just use the default download method for git repositories
-                // found in the source URIs, since cargo currently cannot
-                // initialize submodules for git dependencies anyway.
-                let url = git::git_to_yocto_git_url(
-                    src_id.url().as_str(),
-                    Some(pkg.name().as_str()),
-                    git::GitPrefix::default(),
+    // compare against the recipes on disk instead of writing them
+    if options.check {
+        let mut stale = vec![];
+        for recipe in &recipes {
+            let existing = fs::read_to_string(&recipe.path).ok();
+            if existing.as_ref() == Some(&recipe.contents) {
+                continue;
+            }
+
+            // the diff is the output, -q leaves just the exit status
+            let path = recipe.path.display().to_string();
+            let mut shell = config.shell();
+            if shell.verbosity() != Verbosity::Quiet {
+                let diff = diff::unified(
+                    &path,
+                    &format!("{} (generated)", path),
+                    existing.as_deref().unwrap_or(""),
+                    &recipe.contents,
                 );
+                shell
+                    .out()
+                    .write_all(diff.as_bytes())
+                    .map_err(|e| anyhow!("Unable to write the diff to stdout: {}", e))?;
+            }
+            stale.push(path);
+        }
+
+        if stale.is_empty() {
+            return Ok(());
+        }
+        return Err(anyhow!("Recipes are out of date: {}", stale.join(", ")).into());
+    }
+
+    if let Some(ref path) = options.emit_json {
+        json::write(path, &generated.dependencies)?;
+    }
 
-                // save revision
-                src_uri_extras.push(format!("SRCREV_FORMAT .= \"_{}\"", pkg.name()));
-                let rev = match *src_id.git_reference()? {
-                    GitReference::Tag(ref s) | GitReference::Rev(ref s) => s.to_owned(),
-                    GitReference::Branch(ref s) => {
-                        if s == "master" {
-                            String::from("${AUTOREV}")
-                        } else {
-                            s.to_owned()
-                        }
-                    }
-                    GitReference::DefaultBranch => String::from("${AUTOREV}"),
-                };
-
-                src_uri_extras.push(format!("SRCREV_{} = \"{}\"", pkg.name(), rev));
-                // instruct Cargo where to find this
-                src_uri_extras.push(format!(
-                    "EXTRA_OECARGO_PATHS += \"${{WORKDIR}}/{}\"",
-                    pkg.name()
-                ));
-
-                Some(format!("    {} \\\n", url))
-            } else {
-                Some(format!("    {} \\\n", src_id.url().to_string()))
+    if options.stdout {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let delimit = recipes.len() > 1;
+        for recipe in recipes {
+            // with multiple recipes make it clear where each one starts
+            if delimit {
+                writeln!(out, "# ==> {} <==", recipe.path.display())
+                    .map_err(|e| anyhow!("Unable to write bitbake recipe to stdout: {}", e))?;
             }
-        })
-        .collect::<Vec<String>>();
-
-    // sort the crate list
-    src_uris.sort();
-
-    // root package metadata
-    let metadata = package.manifest().metadata();
-
-    // package description is used as BitBake summary
-    let summary = metadata.description.as_ref().map_or_else(
-        || {
-            println!("No package.description set in your Cargo.toml, using package.name");
-            package.name()
-        },
-        |s| cargo::util::interning::InternedString::new(s.trim()),
-    );
-
-    // package homepage (or source code location)
-    let homepage = metadata
-        .homepage
-        .as_ref()
-        .map_or_else(
-            || {
-                println!("No package.homepage set in your Cargo.toml, trying package.repository");
-                metadata
-                    .repository
-                    .as_ref()
-                    .ok_or_else(|| anyhow!("No package.repository set in your Cargo.toml"))
-            },
-            |s| Ok(s),
-        )?
-        .trim();
-
-    // package license
-    let license = metadata.license.as_ref().map_or_else(
-        || {
-            println!("No package.license set in your Cargo.toml, trying package.license_file");
-            metadata.license_file.as_ref().map_or_else(
-                || {
-                    println!("No package.license_file set in your Cargo.toml");
-                    println!("Assuming {} license", license::CLOSED_LICENSE);
-                    license::CLOSED_LICENSE
-                },
-                |s| s.as_str(),
+            out.write_all(recipe.contents.as_bytes())
+                .map_err(|e| anyhow!("Unable to write bitbake recipe to stdout: {}", e))?;
+        }
+        return Ok(());
+    }
+
+    // where the recipes get written, defaulting to the current directory
+    let output_dir = options.output_dir.clone().unwrap_or_default();
+    if options.output_dir.is_some() {
+        fs::create_dir_all(&output_dir).map_err(|e| {
+            anyhow!(
+                "Unable to create output directory '{}': {}",
+                output_dir.display(),
+                e
             )
-        },
-        |s| s.as_str(),
-    );
-
-    // compute the relative directory into the repo our Cargo.toml is at
-    let rel_dir = md.rel_dir()?;
-
-    // license files for the package
-    let mut lic_files = vec![];
-    let licenses: Vec<&str> = license.split('/').collect();
-    let single_license = licenses.len() == 1;
-    for lic in licenses {
-        lic_files.push(format!(
-            "    {}",
-            license::file(crate_root, &rel_dir, lic, single_license)
-        ));
+        })?;
     }
 
-    // license data in Yocto fmt
-    let license = license.split('/').map(|f| f.trim()).join(" | ");
-
-    // attempt to figure out the git repo for this project
-    let project_repo = git::ProjectRepo::new(config).unwrap_or_else(|e| {
-        println!("{}", e);
-        Default::default()
-    });
-
-    // if this is not a tag we need to include some data about the version in PV so that
-    // the sstate cache remains valid
-    let git_srcpv = if project_repo.tag && project_repo.rev.len() > 10 {
-        // its a tag so nothing needed
-        "".into()
-    } else {
-        // we should be using ${SRCPV} here but due to a bitbake bug we cannot. see:
-        // https://github.com/meta-rust/meta-rust/issues/136
-
-        format!(
-            "PV_append = \".AUTOINC+{}\"",
-            project_repo.rev.split_at(10).0
-        )
-    };
+    for recipe in recipes {
+        // the existing recipe is only replaced once the new one is written
+        output::write(&recipe.path, recipe.contents.as_bytes()).map_err(|e| {
+            anyhow!(
+                "Unable to write bitbake recipe file '{}': {}",
+                recipe.path.display(),
+                e
+            )
+        })?;
 
-    // Iterate over templates and apply the data to each one.
-    if let Some(templates) = templates {
-        for template in templates {
-            let file = PathBuf::from(template.file_stem().unwrap());
-            let ext = file.extension().unwrap().to_str().unwrap();
-            let mut file_str = File::open(template).unwrap();
-            let mut template = String::new();
-            file_str.read_to_string(&mut template).unwrap();
-            
-            let recipe_path = PathBuf::from(format!("{}_{}.{}", package.name(), package.version(), ext));
-            let mut file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&recipe_path)
-                .map_err(|e| anyhow!("Unable to open bitbake recipe file with: {}", e))?;
-
-            template!(
-                &mut template,
-                name = package.name(),
-                version = package.version(),
-                summary = summary,
-                homepage = homepage,
-                license = license,
-                lic_files = lic_files.join(""),
-                src_uri = src_uris.join(""),
-                src_uri_extras = src_uri_extras.join("\n"),
-                project_rel_dir = rel_dir.display(),
-                project_src_uri = project_repo.uri,
-                project_src_rev = project_repo.rev,
-                git_srcpv = git_srcpv,
-                cargo_bitbake_ver = env!("CARGO_PKG_VERSION"),
-            );
-            println!("Template: {}", template);
-
-            println!("Wrote: {}", recipe_path.display());
-            file.write(&template.as_bytes())
-                .map_err(|e| anyhow!("Unable to write bitbake recipe: {}", e))?;
-        }
+        config.shell().status("Wrote", recipe.path.display())?;
+    }
 
-    } else {
-        // build up the path
-        let recipe_path = PathBuf::from(format!("{}_{}.bb", package.name(), package.version()));
-
-        // Open the file where we'll write the BitBake recipe
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&recipe_path)
-        // CliResult accepts only failure::Error, not failure::Context
-        .map_err(|e| anyhow!("Unable to open bitbake recipe file with: {}", e))?;
-
-        // write the contents out
-        write!(
-            file,
-            include_str!("bitbake.template"),
-            name = package.name(),
-            version = package.version(),
-            summary = summary,
-            homepage = homepage,
-            license = license,
-            lic_files = lic_files.join(""),
-            src_uri = src_uris.join(""),
-            src_uri_extras = src_uri_extras.join("\n"),
-            project_rel_dir = rel_dir.display(),
-            project_src_uri = project_repo.uri,
-            project_src_rev = project_repo.rev,
-            git_srcpv = git_srcpv,
-            cargo_bitbake_ver = env!("CARGO_PKG_VERSION"),
-        )
-            .map_err(|e| anyhow!("Unable to write to bitbake recipe file with: {}", e))?;
-
-        println!("Wrote: {}", recipe_path.display());
+    if options.emit_lockfile {
+        match generated.lockfile {
+            Some(ref lockfile) => {
+                let emitted = output_dir.join("Cargo.lock");
+                fs::read(lockfile)
+                    .and_then(|contents| output::write(&emitted, &contents))
+                    .map_err(|e| anyhow!("Unable to write '{}': {}", emitted.display(), e))?;
+                config.shell().status("Wrote", emitted.display())?;
+            }
+            None => config
+                .shell()
+                .warn("The crate was published without a Cargo.lock so none was written")?,
+        }
     }
-    
+
     Ok(())
 }
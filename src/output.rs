@@ -0,0 +1,74 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Writes the generated files so that an existing file is only replaced
+//! once its new contents have been written in full.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// The temporary file `path` is written to before being moved into
+/// place, which is in the same directory so that the rename is atomic
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()))
+}
+
+/// Writes `contents` to the file at `path` by way of a temporary file that
+/// is renamed over it, leaving any existing file alone if writing fails
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+    let result = File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replaces_file() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        let path = dir.join("foo_0.1.0.bb");
+        fs::write(&path, "old\n").unwrap();
+
+        write(&path, b"new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn failure_keeps_file() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        // a directory can't be renamed over with a file
+        let path = dir.join("foo_0.1.0.bb");
+        fs::create_dir_all(&path).unwrap();
+
+        assert!(write(&path, b"new\n").is_err());
+        assert!(path.is_dir());
+        assert!(!temp_path(&path).exists());
+    }
+}
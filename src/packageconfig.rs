@@ -0,0 +1,56 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Maps the features of a crate onto PACKAGECONFIG so they can be
+//! toggled the Yocto way.
+
+/// Generates a PACKAGECONFIG option for each of `features`, enabling the
+/// ones in `defaults` unless the recipe is told otherwise. Cargo's own
+/// default features are turned off so that PACKAGECONFIG is in control.
+pub fn packageconfig(features: &[String], defaults: &[String]) -> String {
+    if features.is_empty() {
+        return String::new();
+    }
+
+    let mut lines = vec![
+        "CARGO_BUILD_FLAGS += \"--no-default-features\"".to_string(),
+        format!("PACKAGECONFIG ??= \"{}\"", defaults.join(" ")),
+    ];
+    for feature in features {
+        lines.push(format!(
+            "PACKAGECONFIG[{}] = \"--features {},,,\"",
+            feature, feature
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn features() {
+        let features = vec!["json".to_string(), "tls".to_string()];
+        let defaults = vec!["tls".to_string()];
+        assert_eq!(
+            packageconfig(&features, &defaults),
+            "CARGO_BUILD_FLAGS += \"--no-default-features\"\n\
+             PACKAGECONFIG ??= \"tls\"\n\
+             PACKAGECONFIG[json] = \"--features json,,,\"\n\
+             PACKAGECONFIG[tls] = \"--features tls,,,\""
+        );
+    }
+
+    #[test]
+    fn no_features() {
+        assert_eq!(packageconfig(&[], &[]), "");
+    }
+}
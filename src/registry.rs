@@ -0,0 +1,347 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use anyhow::anyhow;
+use cargo::core::registry::{PackageRegistry, Registry};
+use cargo::core::{Dependency, Package, SourceId};
+use cargo::util::{CargoResult, IntoUrl};
+use cargo::Config;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// host used in the crate:// URIs for crates from crates.io
+pub const CRATES_IO_URL: &str = "crates.io";
+
+/// host serving the crates.io index over the sparse protocol
+const CRATES_IO_SPARSE_HOST: &str = "index.crates.io";
+
+/// git repository holding the crates.io index
+const CRATES_IO_GIT_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// prefix cargo puts on the index URL of registries using the sparse protocol
+const SPARSE_PREFIX: &str = "sparse+";
+
+/// prefix of the source of registries with a git index, as written in Cargo.lock
+const REGISTRY_PREFIX: &str = "registry+";
+
+/// name cargo gives crates.io in the `[source]` configuration
+const CRATES_IO_SOURCE: &str = "crates-io";
+
+/// How the name and version of a crate are laid out in its crate:// URI,
+/// which depends on the version of the fetcher
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UriFormat {
+    /// `crate://<host>/<name>/<version>`
+    Slash,
+    /// `crate://<host>/<name>-<version>`
+    Hyphen,
+}
+
+impl UriFormat {
+    /// the values accepted for `--crate-uri-format`
+    pub const VARIANTS: &'static [&'static str] = &["slash", "hyphen"];
+
+    /// Provides the crate:// URI for version `version` of the crate
+    /// called `name` from the registry at `host`
+    pub fn crate_uri(self, host: &str, name: &str, version: &str) -> String {
+        match self {
+            UriFormat::Slash => format!("crate://{}/{}/{}", host, name, version),
+            UriFormat::Hyphen => format!("crate://{}/{}-{}", host, name, version),
+        }
+    }
+}
+
+impl FromStr for UriFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<UriFormat, String> {
+        match s {
+            "slash" => Ok(UriFormat::Slash),
+            "hyphen" => Ok(UriFormat::Hyphen),
+            _ => Err(format!("'{}' is not one of slash or hyphen", s)),
+        }
+    }
+}
+
+/// Provides the name the entry in SRC_URI for version `version` of the
+/// crate `name` is given, which its checksums are keyed on
+pub fn fetch_name(name: &str, version: &str) -> String {
+    format!("{}-{}", name, version)
+}
+
+/// Provides the SRC_URI entry fetching version `version` of the crate
+/// `name` from `url`, naming it so that its checksums bind to it even when
+/// crates share a basename
+pub fn src_uri(url: &str, name: &str, version: &str) -> String {
+    format!("{};name={}", url, fetch_name(name, version))
+}
+
+/// A `[source.<name>]` entry of the cargo configuration
+#[derive(Deserialize)]
+struct SourceDef {
+    #[serde(rename = "replace-with")]
+    replace_with: Option<String>,
+    registry: Option<String>,
+}
+
+/// Determines the host to use in the crate:// URI for a package that
+/// comes from the registry identified by `src_id`
+pub fn crate_host(src_id: SourceId) -> String {
+    if src_id.is_default_registry() {
+        return CRATES_IO_URL.into();
+    }
+
+    index_host(src_id.url().as_str()).unwrap_or_else(|| CRATES_IO_URL.into())
+}
+
+/// Provides the host of the registry index at `index`
+fn index_host(index: &str) -> Option<String> {
+    // sparse registries have their protocol prefixed onto the scheme of the
+    // index URL and git indexes may be given as their source so strip those
+    // before finding the host
+    let index = index
+        .trim_start_matches(SPARSE_PREFIX)
+        .trim_start_matches(REGISTRY_PREFIX);
+    if index.trim_end_matches('/') == CRATES_IO_GIT_INDEX {
+        return Some(CRATES_IO_URL.into());
+    }
+
+    let host = index
+        .into_url()
+        .ok()
+        .and_then(|url| url.host_str().map(String::from));
+
+    match host {
+        Some(ref host) if host == CRATES_IO_SPARSE_HOST => Some(CRATES_IO_URL.into()),
+        host => host,
+    }
+}
+
+/// Follows the `replace-with` source replacement configured for the
+/// registry `src_id`, providing the host of the mirror cargo really
+/// fetches its crates from. Nothing is provided when the registry isn't
+/// replaced or is replaced by something other than a registry, such as a
+/// directory of vendored crates.
+pub fn mirror_host(config: &Config, src_id: SourceId) -> CargoResult<Option<String>> {
+    let sources = config
+        .get::<Option<BTreeMap<String, SourceDef>>>("source")?
+        .unwrap_or_default();
+
+    // find what the registry is called in the configuration
+    let url = src_id.url().as_str();
+    let mut name = if src_id.is_default_registry() {
+        Some(CRATES_IO_SOURCE)
+    } else {
+        sources
+            .iter()
+            .find(|(_, def)| def.registry.as_deref() == Some(url))
+            .map(|(name, _)| name.as_str())
+    };
+
+    // follow the chain of replacements, which cargo rejects if it loops
+    let mut replaced = None;
+    for _ in 0..sources.len() {
+        match name
+            .and_then(|n| sources.get(n))
+            .and_then(|def| def.replace_with.as_ref())
+        {
+            Some(replacement) => {
+                replaced = sources.get(replacement.as_str());
+                name = Some(replacement.as_str());
+            }
+            None => break,
+        }
+    }
+
+    Ok(replaced
+        .and_then(|def| def.registry.as_ref())
+        .and_then(|index| index_host(index)))
+}
+
+/// Downloads the crate `spec`, given as `name` or `name@version`, from
+/// crates.io providing its package, extracted into the cargo cache.
+/// Without a version the newest one is used.
+pub fn download_crate(config: &Config, spec: &str) -> CargoResult<Package> {
+    let source_id = SourceId::crates_io(config)?;
+    let dep = crate_dependency(spec, source_id)?;
+
+    let _lock = config.acquire_package_cache_lock()?;
+    let mut registry = PackageRegistry::new(config)?;
+    registry.add_sources(vec![source_id])?;
+
+    let id = registry
+        .query_vec(&dep, false)?
+        .iter()
+        .map(|summary| summary.package_id())
+        .max_by_key(|id| id.version().clone())
+        .ok_or_else(|| anyhow!("No crate matching '{}' on {}", spec, CRATES_IO_URL))?;
+
+    let packages = registry.get(&[id])?;
+    let package = packages.get_one(id)?;
+    Ok(package.clone())
+}
+
+/// Provides the dependency on the crate `spec`, given as `name` or
+/// `name@version`, from `source_id`. A version means exactly that version
+/// rather than any compatible one.
+fn crate_dependency(spec: &str, source_id: SourceId) -> CargoResult<Dependency> {
+    let (name, version) = match spec.find('@') {
+        Some(idx) => (&spec[..idx], Some(format!("={}", &spec[idx + 1..]))),
+        None => (spec, None),
+    };
+    Dependency::parse_no_deprecated(name, version.as_deref(), source_id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cargo::core::PackageId;
+
+    fn registry(url: &str) -> SourceId {
+        SourceId::for_registry(&url.into_url().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn crates_io() {
+        let src_id = SourceId::crates_io(&cargo::Config::default().unwrap()).unwrap();
+        assert_eq!(crate_host(src_id), "crates.io");
+    }
+
+    #[test]
+    fn uri_format() {
+        assert_eq!(
+            UriFormat::Slash.crate_uri("crates.io", "serde", "1.0.0"),
+            "crate://crates.io/serde/1.0.0"
+        );
+        let format = "hyphen".parse::<UriFormat>().unwrap();
+        assert_eq!(
+            format.crate_uri("crates.io", "serde", "1.0.0"),
+            "crate://crates.io/serde-1.0.0"
+        );
+        assert!("dash".parse::<UriFormat>().is_err());
+    }
+
+    #[test]
+    fn src_uri_name() {
+        let url = UriFormat::Slash.crate_uri("crates.io", "serde", "1.0.0");
+        assert_eq!(
+            src_uri(&url, "serde", "1.0.0"),
+            "crate://crates.io/serde/1.0.0;name=serde-1.0.0"
+        );
+        assert_eq!(fetch_name("serde", "1.0.0"), "serde-1.0.0");
+    }
+
+    #[test]
+    fn exact_crate_version() {
+        let src_id = SourceId::crates_io(&cargo::Config::default().unwrap()).unwrap();
+        let matching = |spec: &str| {
+            let dep = crate_dependency(spec, src_id).unwrap();
+            ["1.0.190", "1.0.191", "1.1.0"]
+                .iter()
+                .filter(|version| {
+                    dep.matches_id(PackageId::new("serde", **version, src_id).unwrap())
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching("serde@1.0.190"), vec!["1.0.190"]);
+        assert_eq!(matching("serde"), vec!["1.0.190", "1.0.191", "1.1.0"]);
+    }
+
+    #[test]
+    fn sparse_crates_io() {
+        let src_id = registry("sparse+https://index.crates.io/");
+        assert_eq!(crate_host(src_id), "crates.io");
+    }
+
+    #[test]
+    fn sparse_alternative() {
+        let src_id = registry("sparse+https://crates.example.com/index/");
+        assert_eq!(crate_host(src_id), "crates.example.com");
+    }
+
+    #[test]
+    fn git_index_alternative() {
+        let src_id = registry("https://git.example.com/crates/index.git");
+        assert!(!src_id.is_default_registry());
+        assert_eq!(crate_host(src_id), "git.example.com");
+
+        let src_id = registry("ssh://git@git.example.com/crates/index.git");
+        assert_eq!(crate_host(src_id), "git.example.com");
+    }
+
+    #[test]
+    fn git_index_source() {
+        assert_eq!(
+            index_host("registry+https://git.example.com/crates/index"),
+            Some("git.example.com".into())
+        );
+        assert_eq!(
+            index_host("registry+https://github.com/rust-lang/crates.io-index"),
+            Some("crates.io".into())
+        );
+    }
+
+    /// cargo configuration read from a `.cargo/config.toml` holding `contents`
+    fn config(root: &std::path::Path, contents: &str) -> Config {
+        std::fs::create_dir_all(root.join(".cargo")).unwrap();
+        std::fs::write(root.join(".cargo").join("config.toml"), contents).unwrap();
+        Config::new(cargo::core::Shell::new(), root.into(), root.into())
+    }
+
+    #[test]
+    fn mirror_replaced() {
+        let tmp = crate::testing::temp_dir();
+        let config = config(
+            tmp.path(),
+            r#"
+            [source.crates-io]
+            replace-with = "mirror"
+
+            [source.mirror]
+            registry = "https://mirror.example.com/crates.io-index"
+            "#,
+        );
+        let src_id = SourceId::crates_io(&config).unwrap();
+
+        // the recipe still points at crates.io unless asked for the mirror
+        assert_eq!(crate_host(src_id), "crates.io");
+        assert_eq!(
+            mirror_host(&config, src_id).unwrap(),
+            Some("mirror.example.com".into())
+        );
+    }
+
+    #[test]
+    fn mirror_vendored() {
+        let tmp = crate::testing::temp_dir();
+        let config = config(
+            tmp.path(),
+            r#"
+            [source.crates-io]
+            replace-with = "vendored"
+
+            [source.vendored]
+            directory = "vendor"
+            "#,
+        );
+        let src_id = SourceId::crates_io(&config).unwrap();
+        assert_eq!(mirror_host(&config, src_id).unwrap(), None);
+    }
+
+    #[test]
+    fn mirror_none() {
+        let tmp = crate::testing::temp_dir();
+        let config = config(tmp.path(), "");
+        let src_id = SourceId::crates_io(&config).unwrap();
+        assert_eq!(mirror_host(&config, src_id).unwrap(), None);
+    }
+}
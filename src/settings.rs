@@ -0,0 +1,89 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Settings a project keeps alongside its sources so that everyone
+//! generating its recipes gets the same output without extra arguments.
+
+use anyhow::anyhow;
+use cargo::util::CargoResult;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the settings file in the project or workspace root
+pub const FILE_NAME: &str = ".cargo-bitbake.toml";
+
+/// The contents of a settings file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Template used when none are given with `-t`, instead of the
+    /// built-in one. Relative paths are relative to the settings file.
+    pub default_template: Option<PathBuf>,
+}
+
+/// Finds the settings file closest to `dir`, looking in it and each of its
+/// parents up to `root`
+pub fn find(dir: &Path, root: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .take_while(|d| d.starts_with(root))
+        .map(|d| d.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Reads the settings file at `path`
+pub fn read(path: &Path) -> CargoResult<Settings> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Unable to read '{}': {}", path.display(), e))?;
+    let mut settings: Settings = toml::from_str(&contents)
+        .map_err(|e| anyhow!("Unable to parse '{}': {}", path.display(), e))?;
+
+    let dir = path.parent().expect("settings file must have a parent");
+    settings.default_template = settings.default_template.map(|t| dir.join(t));
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn workspace_default_template() {
+        let tmp = crate::testing::temp_dir();
+        let root = tmp.path().to_path_buf();
+        let member = root.join("member");
+        fs::create_dir_all(&member).unwrap();
+        assert_eq!(find(&member, &root), None);
+
+        fs::write(
+            root.join(FILE_NAME),
+            "default_template = \"templates/recipe.bb\"\n",
+        )
+        .unwrap();
+        let path = find(&member, &root).unwrap();
+        assert_eq!(path, root.join(FILE_NAME));
+        assert_eq!(
+            read(&path).unwrap().default_template,
+            Some(root.join("templates").join("recipe.bb"))
+        );
+
+        // nothing above the workspace root is used
+        assert_eq!(find(&member, &member), None);
+    }
+
+    #[test]
+    fn unknown_setting() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        let path = dir.join(FILE_NAME);
+        fs::write(&path, "default-template = \"recipe.bb\"\n").unwrap();
+        assert!(read(&path).is_err());
+    }
+}
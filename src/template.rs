@@ -0,0 +1,900 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use anyhow::anyhow;
+use cargo::util::CargoResult;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Create a template string by replacing occurrances of name with value.
+/// We aren't worried about performance, so the copy of the string in replace and then
+/// replacing the original is fine. Could also chain the replacements together.
+macro_rules! template {
+    ($template:expr, $($name:ident = $value:expr),*, $(,)*) => {
+        $(
+            let val = $value.to_string();
+            let name = concat!("{", stringify!($name), "}");
+            let temp = $template.replace(&name, &val);
+            *$template = temp;
+        )*
+    }
+}
+
+/// suffixes that mark a file as a template but aren't part of the
+/// extension of the recipe it produces
+const TEMPLATE_SUFFIXES: &[&str] = &["template", "tmpl"];
+
+/// template path standing for a template read from stdin, a file that is
+/// really called `-` can be given as `./-`
+pub const STDIN: &str = "-";
+
+/// marks the start and end of the front matter at the top of a template
+const FRONT_MATTER_DELIMITER: &str = "---";
+
+/// a `{name}` token in a template, also matching BitBake's `${NAME}`
+/// so that those can be told apart
+const TEMPLATE_TOKEN_STR: &str = r"\$?\{([A-Za-z_][A-Za-z0-9_]*)\}";
+
+/// every token cargo-bitbake provides a value for
+const KNOWN_TOKENS: &[&str] = &[
+    "name",
+    "crate_name",
+    "cargo_crate_name",
+    "version",
+    "crate_version",
+    "summary",
+    "description",
+    "homepage",
+    "license",
+    "lic_files",
+    "src_uri",
+    "src_uri_extras",
+    "project_rel_dir",
+    "project_src_dir",
+    "project_subdir",
+    "project_src_uri",
+    "project_src_rev",
+    "git_srcpv",
+    "rust_version",
+    "edition",
+    "cargo_inherit",
+    "depends",
+    "packageconfig",
+    "classextend",
+    "all_licenses",
+    "bins",
+    "default_bin",
+    "files",
+    "continuation",
+    "rustflags",
+    "dep_count",
+    "max_depth",
+    "cargo_bitbake_ver",
+];
+
+/// tokens one of the templates has to use for the dependencies to be fetched
+const REQUIRED_TOKENS: &[&str] = &["src_uri", "src_uri_extras"];
+
+/// longest `SUMMARY` taken from a crate's description, BitBake suggests
+/// keeping it under 80 characters
+const SUMMARY_MAX_LEN: usize = 72;
+
+lazy_static! {
+    static ref TEMPLATE_TOKEN: Regex = Regex::new(TEMPLATE_TOKEN_STR).unwrap();
+    /// stdin can only be read once but the template is used for every recipe
+    static ref STDIN_TEMPLATE: Result<String, String> = {
+        let mut contents = String::new();
+        io::stdin()
+            .read_to_string(&mut contents)
+            .map(|_| contents)
+            .map_err(|e| e.to_string())
+    };
+}
+
+/// The values that get filled into a template to produce a recipe
+#[derive(Debug, Default)]
+pub struct TemplateVars {
+    pub name: String,
+    pub crate_name: String,
+    pub cargo_crate_name: String,
+    pub version: String,
+    pub crate_version: String,
+    pub summary: String,
+    pub description: String,
+    pub homepage: String,
+    pub license: String,
+    pub lic_files: String,
+    pub src_uri: String,
+    pub src_uri_extras: String,
+    pub project_rel_dir: String,
+    pub project_src_dir: String,
+    pub project_subdir: String,
+    pub project_src_uri: String,
+    pub project_src_rev: String,
+    pub git_srcpv: String,
+    pub rust_version: String,
+    pub edition: String,
+    pub cargo_inherit: String,
+    pub depends: String,
+    pub packageconfig: String,
+    pub classextend: String,
+    /// every license of the package and its dependencies joined with `&`
+    pub all_licenses: String,
+    /// names of the binaries the package builds, separated by spaces
+    pub bins: String,
+    /// the binary `cargo run` uses, its `default-run` or its only binary
+    pub default_bin: String,
+    /// a `FILES_${PN}` line for each binary, commented out unless asked for
+    pub files: String,
+    /// what ends the lines of values spanning several lines, ` \` by default
+    pub continuation: String,
+    /// the rustflags cargo is configured to build with, separated by spaces
+    pub rustflags: String,
+    /// number of crates the recipe fetches, excluding path dependencies
+    pub dep_count: usize,
+    /// length of the longest chain of dependencies from the package
+    pub max_depth: usize,
+    /// extra `name=value` pairs from `--define` for custom templates
+    pub defines: Vec<(String, String)>,
+}
+
+impl TemplateVars {
+    /// the file name of the recipe with the extension `ext`
+    fn recipe_file(&self, ext: &str) -> PathBuf {
+        PathBuf::from(format!("{}_{}.{}", self.name, self.version, ext))
+    }
+}
+
+/// Determines the extension of the recipe produced by the template at `path`.
+/// Templates are named `<anything>.<ext>` optionally followed by a `.template`
+/// or `.tmpl` suffix, e.g. `foo.bb.template` or `foo.bbappend.tmpl`.
+fn recipe_extension(path: &Path) -> CargoResult<String> {
+    let file_name = path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .ok_or_else(|| anyhow!("Invalid template file name '{}'", path.display()))?;
+
+    let mut parts = file_name.split('.').collect::<Vec<_>>();
+    if let Some(suffix) = parts.last() {
+        if parts.len() > 1 && TEMPLATE_SUFFIXES.contains(suffix) {
+            parts.pop();
+        }
+    }
+
+    match parts.last() {
+        Some(ext) if parts.len() > 1 && !ext.is_empty() => Ok(ext.to_string()),
+        _ => Err(anyhow!(
+            "Unable to determine the recipe extension for template '{}'",
+            path.display()
+        )),
+    }
+}
+
+/// Settings a template can declare in a block of TOML front matter
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    /// extension of the recipe, instead of taking it from the file name
+    extension: Option<String>,
+    /// values for tokens, which `--define` takes precedence over
+    #[serde(default)]
+    defines: BTreeMap<String, String>,
+}
+
+/// A template read from disk with its front matter split off
+struct Template<'a> {
+    path: &'a Path,
+    settings: FrontMatter,
+    body: String,
+}
+
+impl<'a> Template<'a> {
+    /// Reads the template at `path` and parses its front matter
+    fn load(path: &'a Path) -> CargoResult<Template<'a>> {
+        let contents = read(path)?;
+        let (front_matter, body) = split_front_matter(&contents);
+        let settings = match front_matter {
+            Some(Ok(front_matter)) => toml::from_str(front_matter).map_err(|e| {
+                anyhow!(
+                    "Invalid front matter in template '{}': {}",
+                    path.display(),
+                    e
+                )
+            })?,
+            Some(Err(())) => {
+                return Err(anyhow!(
+                    "Front matter in template '{}' has no closing '{}'",
+                    path.display(),
+                    FRONT_MATTER_DELIMITER
+                ))
+            }
+            None => FrontMatter::default(),
+        };
+
+        Ok(Template {
+            path,
+            settings,
+            body: body.to_string(),
+        })
+    }
+
+    /// the extension of the recipe the template produces. A template read
+    /// from stdin has no file name to take it from so it is given as
+    /// `stdin_extension` instead, unless it declares one.
+    fn extension(&self, stdin_extension: Option<&str>) -> CargoResult<String> {
+        let declared = match stdin_extension {
+            Some(ext) if is_stdin(self.path) => Some(ext),
+            _ => self.settings.extension.as_deref(),
+        };
+        match declared {
+            Some(ext) if !ext.trim_start_matches('.').is_empty() => {
+                Ok(ext.trim_start_matches('.').to_string())
+            }
+            Some(_) => Err(anyhow!(
+                "Template '{}' declares an empty extension",
+                self.path.display()
+            )),
+            None if is_stdin(self.path) => Err(anyhow!(
+                "The extension of the recipe produced by the template on stdin must be given with --extension"
+            )),
+            None => recipe_extension(self.path),
+        }
+    }
+}
+
+/// Splits the `---` delimited front matter from the top of `contents`,
+/// providing it if there is any along with the rest of the template.
+/// Front matter that is never closed is an error.
+fn split_front_matter(contents: &str) -> (Option<Result<&str, ()>>, &str) {
+    let mut lines = contents.lines();
+    if lines.next().map(str::trim_end) != Some(FRONT_MATTER_DELIMITER) {
+        return (None, contents);
+    }
+
+    // track where each line starts to be able to slice out the pieces
+    let start = contents.find('\n').map_or(contents.len(), |idx| idx + 1);
+    let mut offset = start;
+    for line in contents[start..].split('\n') {
+        let next = (offset + line.len() + 1).min(contents.len());
+        if line.trim_end() == FRONT_MATTER_DELIMITER {
+            return (Some(Ok(&contents[start..offset])), &contents[next..]);
+        }
+        offset = next;
+    }
+
+    (Some(Err(())), contents)
+}
+
+/// Ensures the template at `path` exists and produces a recipe with
+/// a known extension so problems are reported before doing any work
+pub fn check(path: &Path, stdin_extension: Option<&str>) -> CargoResult<()> {
+    if !is_stdin(path) && !path.is_file() {
+        return Err(anyhow!("Template '{}' does not exist", path.display()));
+    }
+    Template::load(path)?.extension(stdin_extension).map(|_| ())
+}
+
+/// Whether the template `path` is the one read from stdin
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN
+}
+
+/// Reads the template at `path`
+fn read(path: &Path) -> CargoResult<String> {
+    if is_stdin(path) {
+        return STDIN_TEMPLATE
+            .clone()
+            .map_err(|e| anyhow!("Unable to read template from stdin: {}", e));
+    }
+    fs::read_to_string(path)
+        .map_err(|e| anyhow!("Unable to read template '{}': {}", path.display(), e))
+}
+
+/// Problems with the tokens used by a set of templates
+#[derive(Debug, Default, PartialEq)]
+pub struct Placeholders {
+    /// tokens the recipe needs that none of the templates use
+    pub missing: Vec<&'static str>,
+    /// tokens the templates use that nothing provides a value for
+    pub unknown: Vec<String>,
+}
+
+/// Checks the tokens used by the `templates` rendered together against
+/// those cargo-bitbake knows about and the names given with `--define`.
+/// Required tokens only need to be used by one of them, such as when a
+/// `.bb` includes a generated `.inc` holding the `SRC_URI`.
+pub fn placeholders(
+    templates: &[PathBuf],
+    defines: &[(String, String)],
+) -> CargoResult<Placeholders> {
+    let mut tokens = vec![];
+    for path in templates {
+        let template = Template::load(path)?;
+        tokens.extend(
+            unresolved(&template.body)
+                .into_iter()
+                .filter(|t| !template.settings.defines.contains_key(t)),
+        );
+    }
+    tokens.sort();
+    tokens.dedup();
+
+    let missing = REQUIRED_TOKENS
+        .iter()
+        .filter(|&&token| !tokens.iter().any(|t| t == token))
+        .cloned()
+        .collect();
+    let unknown = tokens
+        .into_iter()
+        .filter(|t| !KNOWN_TOKENS.contains(&t.as_str()))
+        .filter(|t| !defines.iter().any(|(name, _)| name == t))
+        .collect();
+
+    Ok(Placeholders { missing, unknown })
+}
+
+/// Renders the template at `path` with `vars`, providing the file name of
+/// the resulting recipe along with its contents. `stdin_extension` is the
+/// extension of the recipe when the template is read from stdin.
+pub fn render_template(
+    path: &Path,
+    stdin_extension: Option<&str>,
+    vars: &TemplateVars,
+) -> CargoResult<(PathBuf, String)> {
+    let loaded = Template::load(path)?;
+    let ext = loaded.extension(stdin_extension)?;
+    let mut template = loaded.body;
+
+    template!(
+        &mut template,
+        name = vars.name,
+        crate_name = vars.crate_name,
+        cargo_crate_name = vars.cargo_crate_name,
+        version = vars.version,
+        crate_version = vars.crate_version,
+        summary = vars.summary,
+        description = vars.description,
+        homepage = vars.homepage,
+        license = vars.license,
+        lic_files = vars.lic_files,
+        src_uri = vars.src_uri,
+        src_uri_extras = vars.src_uri_extras,
+        project_rel_dir = vars.project_rel_dir,
+        project_src_dir = vars.project_src_dir,
+        project_subdir = vars.project_subdir,
+        project_src_uri = vars.project_src_uri,
+        project_src_rev = vars.project_src_rev,
+        git_srcpv = vars.git_srcpv,
+        rust_version = vars.rust_version,
+        edition = vars.edition,
+        cargo_inherit = vars.cargo_inherit,
+        depends = vars.depends,
+        packageconfig = vars.packageconfig,
+        classextend = vars.classextend,
+        all_licenses = vars.all_licenses,
+        bins = vars.bins,
+        default_bin = vars.default_bin,
+        files = vars.files,
+        continuation = vars.continuation,
+        rustflags = vars.rustflags,
+        dep_count = vars.dep_count,
+        max_depth = vars.max_depth,
+        cargo_bitbake_ver = env!("CARGO_PKG_VERSION"),
+    );
+
+    // anything the template needs that cargo-bitbake doesn't know about
+    for (name, value) in &vars.defines {
+        template = template.replace(&format!("{{{}}}", name), value);
+    }
+    // the template's own defaults for anything not given with --define
+    for (name, value) in &loaded.settings.defines {
+        template = template.replace(&format!("{{{}}}", name), value);
+    }
+
+    Ok((vars.recipe_file(&ext), template))
+}
+
+/// Provides a crate's `description` as a single line suitable
+/// for `DESCRIPTION`
+pub fn description(description: &str) -> String {
+    description.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Provides a short `SUMMARY` from a crate's `description` using only its
+/// first line or sentence, truncated on a word boundary if it's still long
+pub fn summary(description: &str) -> String {
+    let line = description.trim().lines().next().unwrap_or("");
+    let sentence = match line.find(". ") {
+        Some(idx) => &line[..=idx],
+        None => line,
+    };
+    let sentence = sentence.trim().trim_end_matches('.');
+
+    if sentence.chars().count() <= SUMMARY_MAX_LEN {
+        return sentence.to_string();
+    }
+
+    let mut summary = String::new();
+    for word in sentence.split_whitespace() {
+        if summary.chars().count() + word.chars().count() + 4 > SUMMARY_MAX_LEN {
+            break;
+        }
+        if !summary.is_empty() {
+            summary.push(' ');
+        }
+        summary.push_str(word);
+    }
+    summary.push_str("...");
+    summary
+}
+
+/// Renders the relative `path` for use in a recipe, which BitBake always
+/// wants separated with `/` whatever the host the recipe is generated on
+pub fn recipe_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Appends `extra` verbatim to the end of the rendered `recipe`,
+/// starting it on a line of its own
+pub fn append(recipe: &mut String, extra: &str) {
+    if !recipe.is_empty() && !recipe.ends_with('\n') {
+        recipe.push('\n');
+    }
+    recipe.push_str(extra);
+}
+
+/// How the lines of values spanning several lines, such as `SRC_URI` and
+/// `LIC_FILES_CHKSUM`, are laid out in a recipe
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Layout {
+    /// what each line is indented with
+    pub indent: String,
+    /// what ends each line so the value continues on the next one
+    pub continuation: String,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            indent: "    ".into(),
+            continuation: " \\".into(),
+        }
+    }
+}
+
+impl Layout {
+    /// lays out `entry` as a line of a value spanning several lines
+    pub fn line(&self, entry: &str) -> String {
+        format!("{}{}{}\n", self.indent, entry, self.continuation)
+    }
+
+    /// lays out each line of `entries`, which end with ` \`, as `line` does
+    pub fn lines(&self, entries: &str) -> Vec<String> {
+        entries
+            .lines()
+            .map(|entry| self.line(entry.trim_end_matches('\\').trim_end()))
+            .collect()
+    }
+}
+
+/// Parses an `--indent` argument, `spaces:<width>` or `tabs:<width>`, into
+/// what lines are indented with
+pub fn parse_indent(indent: &str) -> Result<String, String> {
+    let mut parts = indent.splitn(2, ':');
+    let (kind, width) = (parts.next(), parts.next().map(str::parse::<usize>));
+    match (kind, width) {
+        (Some("spaces"), Some(Ok(width))) => Ok(" ".repeat(width)),
+        (Some("tabs"), Some(Ok(width))) => Ok("\t".repeat(width)),
+        _ => Err(format!(
+            "'{}' is not of the form spaces:<width> or tabs:<width>",
+            indent
+        )),
+    }
+}
+
+/// Parses a `--define` argument of the form `name=value`
+pub fn parse_define(define: &str) -> Result<(String, String), String> {
+    let mut parts = define.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("'{}' is not of the form name=value", define)),
+    }
+}
+
+/// Provides the `{name}` tokens left in a rendered template, which
+/// nothing provided a value for. BitBake's `${VAR}` are not included.
+pub fn unresolved(rendered: &str) -> Vec<String> {
+    let mut tokens = TEMPLATE_TOKEN
+        .captures_iter(rendered)
+        .filter(|c| !c[0].starts_with('$'))
+        .map(|c| c[1].to_string())
+        .collect::<Vec<_>>();
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+/// Renders the built-in `bitbake.template` with `vars`, providing the
+/// file name of the resulting recipe along with its contents
+pub fn render_default(vars: &TemplateVars) -> (PathBuf, String) {
+    // only crates that set a rust-version get a line for it
+    let rust_version = if vars.rust_version.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\n# the oldest rustc the crate supports (package.rust-version)\nCARGO_RUST_VERSION = \"{}\"\n",
+            vars.rust_version
+        )
+    };
+
+    // library only crates have no binaries to install
+    let files = if vars.files.is_empty() {
+        String::new()
+    } else {
+        format!("\n# the binaries the package installs\n{}\n", vars.files)
+    };
+
+    let recipe = format!(
+        include_str!("bitbake.template"),
+        name = vars.name,
+        crate_name = vars.crate_name,
+        cargo_crate_name = vars.cargo_crate_name,
+        crate_version = vars.crate_version,
+        summary = vars.summary,
+        description = vars.description,
+        homepage = vars.homepage,
+        license = vars.license,
+        lic_files = vars.lic_files,
+        src_uri = vars.src_uri,
+        src_uri_extras = vars.src_uri_extras,
+        project_rel_dir = vars.project_rel_dir,
+        project_src_dir = vars.project_src_dir,
+        project_src_uri = vars.project_src_uri,
+        project_src_rev = vars.project_src_rev,
+        git_srcpv = vars.git_srcpv,
+        rust_version = rust_version,
+        edition = vars.edition,
+        cargo_inherit = vars.cargo_inherit,
+        depends = vars.depends,
+        packageconfig = vars.packageconfig,
+        classextend = vars.classextend,
+        files = files,
+        continuation = vars.continuation,
+        cargo_bitbake_ver = env!("CARGO_PKG_VERSION"),
+    );
+
+    (vars.recipe_file("bb"), recipe)
+}
+
+/// Renders the built-in `bbappend.template` with `vars`, which only
+/// carries the dependencies, providing the file name of the resulting
+/// `.bbappend` along with its contents
+pub fn render_bbappend(vars: &TemplateVars) -> (PathBuf, String) {
+    let bbappend = format!(
+        include_str!("bbappend.template"),
+        crate_name = vars.crate_name,
+        src_uri = vars.src_uri,
+        src_uri_extras = vars.src_uri_extras,
+        continuation = vars.continuation,
+        cargo_bitbake_ver = env!("CARGO_PKG_VERSION"),
+    );
+
+    (vars.recipe_file("bbappend"), bbappend)
+}
+
+/// Finds all the `*.template` files in `dir`, sorted by name
+pub fn templates_in(dir: &Path) -> CargoResult<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        anyhow!(
+            "Unable to read template directory '{}': {}",
+            dir.display(),
+            e
+        )
+    })?;
+
+    let mut templates = vec![];
+    for entry in entries {
+        let path = entry
+            .map_err(|e| {
+                anyhow!(
+                    "Unable to read template directory '{}': {}",
+                    dir.display(),
+                    e
+                )
+            })?
+            .path();
+        if path.is_file() && path.extension() == Some(OsStr::new("template")) {
+            templates.push(path);
+        }
+    }
+    templates.sort();
+
+    Ok(templates)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extension_with_suffix() {
+        let ext = recipe_extension(Path::new("templates/foo.bb.template")).unwrap();
+        assert_eq!(ext, "bb");
+        let ext = recipe_extension(Path::new("foo.bbappend.tmpl")).unwrap();
+        assert_eq!(ext, "bbappend");
+    }
+
+    #[test]
+    fn default_rust_version() {
+        let mut vars = TemplateVars::default();
+        let (_, recipe) = render_default(&vars);
+        assert!(!recipe.contains("CARGO_RUST_VERSION"));
+
+        vars.rust_version = "1.56".into();
+        let (_, recipe) = render_default(&vars);
+        assert!(recipe.contains("CARGO_RUST_VERSION = \"1.56\"\n"));
+    }
+
+    #[test]
+    fn default_files() {
+        let mut vars = TemplateVars::default();
+        let (_, recipe) = render_default(&vars);
+        assert!(!recipe.contains("# the binaries the package installs"));
+
+        vars.files = "# FILES_${PN} += \"${bindir}/tool\"".into();
+        let (_, recipe) = render_default(&vars);
+        assert!(recipe.contains(
+            "# the binaries the package installs\n# FILES_${PN} += \"${bindir}/tool\"\n"
+        ));
+    }
+
+    #[test]
+    fn extension_without_suffix() {
+        let ext = recipe_extension(Path::new("foo.inc")).unwrap();
+        assert_eq!(ext, "inc");
+    }
+
+    #[test]
+    fn missing_template() {
+        let path = Path::new("/nonexistent/foo.bb.template");
+        let err = check(path, None).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/foo.bb.template"));
+
+        let err = render_template(path, None, &TemplateVars::default()).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/foo.bb.template"));
+    }
+
+    #[test]
+    fn extension_stdin() {
+        let mut template = Template {
+            path: Path::new(STDIN),
+            settings: FrontMatter::default(),
+            body: String::new(),
+        };
+        assert_eq!(template.extension(Some("bb")).unwrap(), "bb");
+        assert!(template.extension(None).is_err());
+
+        template.settings.extension = Some("inc".into());
+        assert_eq!(template.extension(None).unwrap(), "inc");
+        assert_eq!(template.extension(Some(".bb")).unwrap(), "bb");
+
+        // only exactly `-` is stdin
+        assert!(!is_stdin(Path::new("./-")));
+        template.path = Path::new("./-");
+        assert_eq!(template.extension(Some("bb")).unwrap(), "inc");
+    }
+
+    #[test]
+    fn extension_missing() {
+        assert!(recipe_extension(Path::new("foo.template")).is_err());
+        assert!(recipe_extension(Path::new("foo")).is_err());
+    }
+
+    #[test]
+    fn front_matter() {
+        let contents = "---\nextension = \"bbappend\"\n---\nSRC_URI += \"{src_uri}\"\n";
+        let (front, body) = split_front_matter(contents);
+        assert_eq!(front, Some(Ok("extension = \"bbappend\"\n")));
+        assert_eq!(body, "SRC_URI += \"{src_uri}\"\n");
+
+        let (front, body) = split_front_matter("SUMMARY = \"{summary}\"\n---\n");
+        assert_eq!(front, None);
+        assert_eq!(body, "SUMMARY = \"{summary}\"\n---\n");
+
+        let (front, _) = split_front_matter("---\nextension = \"bb\"\n");
+        assert_eq!(front, Some(Err(())));
+    }
+
+    #[test]
+    fn front_matter_settings() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        let path = dir.join("library.template");
+        fs::write(
+            &path,
+            "---\nextension = \"bbappend\"\n\n[defines]\nlayer = \"meta-foo\"\nmaintainer = \"nobody\"\n---\n{layer} {maintainer} {name}\n",
+        )
+        .unwrap();
+        check(&path, None).unwrap();
+
+        let vars = TemplateVars {
+            name: "foo".into(),
+            version: "1.0.0".into(),
+            defines: vec![("maintainer".into(), "Jane".into())],
+            ..Default::default()
+        };
+        let (file, recipe) = render_template(&path, None, &vars).unwrap();
+        assert_eq!(file, PathBuf::from("foo_1.0.0.bbappend"));
+        assert_eq!(recipe, "meta-foo Jane foo\n");
+
+        fs::write(&path, "---\nextension = 3\n---\n").unwrap();
+        let err = check(&path, None).unwrap_err();
+        assert!(err.to_string().contains("Invalid front matter"));
+    }
+
+    #[test]
+    fn bbappend() {
+        let vars = TemplateVars {
+            name: "foo".into(),
+            version: "1.0.0".into(),
+            src_uri: "    crate://crates.io/bar/0.1.0 \\\n".into(),
+            ..Default::default()
+        };
+        let (file, bbappend) = render_bbappend(&vars);
+        assert_eq!(file, PathBuf::from("foo_1.0.0.bbappend"));
+        assert!(bbappend.contains("FILESEXTRAPATHS_prepend := \"${THISDIR}/${PN}:\""));
+        assert!(bbappend.contains("crate://crates.io/bar/0.1.0"));
+        assert!(!bbappend.contains("LICENSE"));
+    }
+
+    #[test]
+    fn define() {
+        assert_eq!(
+            parse_define("maintainer=Jane <jane@example.com>"),
+            Ok(("maintainer".into(), "Jane <jane@example.com>".into()))
+        );
+        assert_eq!(parse_define("layer="), Ok(("layer".into(), "".into())));
+        assert!(parse_define("layer").is_err());
+        assert!(parse_define("=foo").is_err());
+    }
+
+    #[test]
+    fn indent() {
+        assert_eq!(parse_indent("spaces:2"), Ok("  ".into()));
+        assert_eq!(parse_indent("tabs:1"), Ok("\t".into()));
+        assert!(parse_indent("tabs").is_err());
+        assert!(parse_indent("dots:1").is_err());
+    }
+
+    #[test]
+    fn layout_lines() {
+        let entries = "file://LICENSE-MIT;md5=aaaa \\\nfile://LICENSE-APACHE;md5=bbbb \\\n";
+        assert_eq!(
+            Layout::default().lines(entries),
+            vec![
+                "    file://LICENSE-MIT;md5=aaaa \\\n",
+                "    file://LICENSE-APACHE;md5=bbbb \\\n"
+            ]
+        );
+
+        let tabs = Layout {
+            indent: "\t".into(),
+            continuation: "\t\\".into(),
+        };
+        assert_eq!(
+            tabs.line("crate://crates.io/bar/0.1.0"),
+            "\tcrate://crates.io/bar/0.1.0\t\\\n"
+        );
+    }
+
+    #[test]
+    fn summary_first_sentence() {
+        let desc = "A fast parser.  Supports streaming\nand a second line.";
+        assert_eq!(summary(desc), "A fast parser");
+        assert_eq!(
+            summary("Serialization framework\nLong text"),
+            "Serialization framework"
+        );
+        assert_eq!(
+            description(desc),
+            "A fast parser. Supports streaming and a second line."
+        );
+    }
+
+    #[test]
+    fn summary_truncated() {
+        let desc = "word ".repeat(30);
+        let short = summary(&desc);
+        assert!(short.len() <= SUMMARY_MAX_LEN);
+        assert!(short.ends_with("word..."));
+    }
+
+    #[test]
+    fn recipe_path_separators() {
+        let path = Path::new("crates").join("member").join("LICENSE-MIT");
+        assert_eq!(recipe_path(&path), "crates/member/LICENSE-MIT");
+        assert_eq!(recipe_path(Path::new("")), "");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn recipe_path_backslashes() {
+        assert_eq!(recipe_path(Path::new(r"crates\member")), "crates/member");
+    }
+
+    #[test]
+    fn append_lines() {
+        let mut recipe = "LICENSE = \"MIT\"".to_string();
+        append(&mut recipe, "INSANE_SKIP_${PN} += \"ldflags\"\n");
+        assert_eq!(
+            recipe,
+            "LICENSE = \"MIT\"\nINSANE_SKIP_${PN} += \"ldflags\"\n"
+        );
+
+        let mut recipe = "LICENSE = \"MIT\"\n".to_string();
+        append(&mut recipe, "LICENSE_FLAGS = \"commercial\"\n");
+        assert_eq!(
+            recipe,
+            "LICENSE = \"MIT\"\nLICENSE_FLAGS = \"commercial\"\n"
+        );
+    }
+
+    #[test]
+    fn unresolved_tokens() {
+        let rendered = "S = \"${WORKDIR}/git\"\nMAINTAINER = \"{maintainer}\"\n{layer}{maintainer}";
+        assert_eq!(unresolved(rendered), vec!["layer", "maintainer"]);
+    }
+
+    #[test]
+    fn adjacent_tokens() {
+        assert_eq!(
+            unresolved("{layer}{maintainer}${PN}"),
+            vec!["layer", "maintainer"]
+        );
+    }
+
+    #[test]
+    fn template_placeholders() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+
+        let bb = dir.join("foo.bb.template");
+        fs::write(&bb, "SRC_URI = \"{src_uri}\"\n{maintainer} {layer} ${PN}\n").unwrap();
+        let defines = vec![("layer".to_string(), "meta-foo".to_string())];
+        assert_eq!(
+            placeholders(std::slice::from_ref(&bb), &defines).unwrap(),
+            Placeholders {
+                missing: vec!["src_uri_extras"],
+                unknown: vec!["maintainer".into()],
+            }
+        );
+
+        // another template rendered alongside can provide the rest
+        let inc = dir.join("foo.inc.template");
+        fs::write(&inc, "{src_uri_extras}\nMAINTAINER = \"{maintainer}\"\n").unwrap();
+        let defines = vec![
+            ("layer".to_string(), "meta-foo".to_string()),
+            ("maintainer".to_string(), "Jane".to_string()),
+        ];
+        assert_eq!(
+            placeholders(&[bb, inc], &defines).unwrap(),
+            Placeholders::default()
+        );
+    }
+}
@@ -0,0 +1,120 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Refreshes the dependencies of a recipe generated earlier, leaving the
+//! rest of it, and any edits made to it, as they are.
+
+use anyhow::anyhow;
+use cargo::util::CargoResult;
+
+/// line the built-in template puts before the dependencies
+const BEGIN_MARKER: &str = "# cargo-bitbake dependencies begin";
+
+/// line the built-in template puts after the dependencies
+const END_MARKER: &str = "# cargo-bitbake dependencies end";
+
+/// Provides the dependencies of a recipe, the crates in `src_uri` and the
+/// `src_uri_extras` with their revisions and checksums, laid out the same
+/// as the built-in template does between its markers with `continuation`
+/// ending the lines of `SRC_URI`
+pub fn dependencies(src_uri: &str, src_uri_extras: &str, continuation: &str) -> String {
+    format!(
+        "SRC_URI += \"{}\n{}\"\n\n{}\n",
+        continuation, src_uri, src_uri_extras
+    )
+}
+
+/// Replaces the dependencies between the markers in the existing `recipe`
+/// with `dependencies`, providing the updated recipe
+pub fn update(recipe: &str, dependencies: &str) -> CargoResult<String> {
+    let begin = line_end(recipe, BEGIN_MARKER, 0)
+        .ok_or_else(|| anyhow!("The recipe has no '{}' line", BEGIN_MARKER))?;
+    let end = line_start(recipe, END_MARKER, begin).ok_or_else(|| {
+        anyhow!(
+            "The recipe has no '{}' line after '{}'",
+            END_MARKER,
+            BEGIN_MARKER
+        )
+    })?;
+
+    Ok(format!(
+        "{}{}{}",
+        &recipe[..begin],
+        dependencies,
+        &recipe[end..]
+    ))
+}
+
+/// Finds where the line holding only `marker` starts, at or after `from`
+fn line_start(recipe: &str, marker: &str, from: usize) -> Option<usize> {
+    let mut offset = from;
+    for line in recipe[from..].split('\n') {
+        if line.trim_end() == marker {
+            return Some(offset);
+        }
+        offset += line.len() + 1;
+    }
+    None
+}
+
+/// Finds where the line after the one holding only `marker` starts
+fn line_end(recipe: &str, marker: &str, from: usize) -> Option<usize> {
+    line_start(recipe, marker, from)
+        .map(|start| start + marker.len())
+        .map(|end| match recipe[end..].find('\n') {
+            Some(newline) => end + newline + 1,
+            None => recipe.len(),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::template::{render_default, TemplateVars};
+
+    #[test]
+    fn round_trip() {
+        let vars = TemplateVars {
+            name: "foo".into(),
+            license: "MIT".into(),
+            src_uri: "    crate://crates.io/bar/0.1.0 \\\n".into(),
+            src_uri_extras: "SRC_URI[bar-0.1.0.sha256sum] = \"aaaa\"".into(),
+            continuation: " \\".into(),
+            ..Default::default()
+        };
+        let (_, recipe) = render_default(&vars);
+        // regenerating the same dependencies doesn't change anything
+        let same = dependencies(&vars.src_uri, &vars.src_uri_extras, &vars.continuation);
+        assert_eq!(update(&recipe, &same).unwrap(), recipe);
+
+        // a hand edit outside of the dependencies is kept
+        let recipe = recipe.replace("LICENSE = \"MIT\"", "LICENSE = \"MIT & BSD-3-Clause\"");
+        let bumped = dependencies(
+            "    crate://crates.io/bar/0.2.0 \\\n",
+            "SRC_URI[bar-0.2.0.sha256sum] = \"bbbb\"",
+            " \\",
+        );
+        let updated = update(&recipe, &bumped).unwrap();
+        assert!(updated.contains("crate://crates.io/bar/0.2.0"));
+        assert!(updated.contains("SRC_URI[bar-0.2.0.sha256sum] = \"bbbb\""));
+        assert!(!updated.contains("bar/0.1.0"));
+        assert!(!updated.contains("aaaa"));
+        assert!(updated.contains("LICENSE = \"MIT & BSD-3-Clause\""));
+    }
+
+    #[test]
+    fn missing_markers() {
+        let err = update("SRC_URI += \"\"\n", "").unwrap_err();
+        assert!(err.to_string().contains(BEGIN_MARKER));
+
+        let err = update(&format!("{}\nSRC_URI += \"\"\n", BEGIN_MARKER), "").unwrap_err();
+        assert!(err.to_string().contains(END_MARKER));
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * Copyright 2016-2017 Doug Goldstein <cardoe@cardoe.com>
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Vendoring of registry crates in the layout `cargo vendor` produces so
+//! that a recipe can fetch a single archive rather than every crate.
+
+use crate::checksum;
+use anyhow::anyhow;
+use cargo::core::{Package, PackageId};
+use cargo::sources::PathSource;
+use cargo::util::CargoResult;
+use cargo::Config;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use xz2::write::XzEncoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+/// Files cargo leaves around in its own checkouts that aren't part of the crate
+const SKIPPED_FILES: &[&str] = &[".cargo-ok", ".gitattributes", ".gitignore", ".git"];
+
+/// How the vendored archive is compressed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Gz,
+    Xz,
+    Zst,
+}
+
+impl Compression {
+    /// the values accepted for `--vendor-compress`
+    pub const VARIANTS: &'static [&'static str] = &["gz", "xz", "zst"];
+
+    /// the extension of the archive, which is also how bitbake's unpack
+    /// knows to decompress it
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gz => "tar.gz",
+            Compression::Xz => "tar.xz",
+            Compression::Zst => "tar.zst",
+        }
+    }
+
+    /// wraps `file` so that everything written to it is compressed
+    fn encoder(self, file: File) -> io::Result<Box<dyn Finish>> {
+        Ok(match self {
+            Compression::Gz => Box::new(GzEncoder::new(file, flate2::Compression::best())),
+            Compression::Xz => Box::new(XzEncoder::new(file, 9)),
+            Compression::Zst => Box::new(ZstdEncoder::new(file, 19)?),
+        })
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Compression, String> {
+        match s {
+            "gz" => Ok(Compression::Gz),
+            "xz" => Ok(Compression::Xz),
+            "zst" => Ok(Compression::Zst),
+            _ => Err(format!("'{}' is not one of gz, xz or zst", s)),
+        }
+    }
+}
+
+/// An encoder that must be told when everything has been written to it
+trait Finish: Write {
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl Finish for GzEncoder<File> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        GzEncoder::finish(*self).map(|_| ())
+    }
+}
+
+impl Finish for XzEncoder<File> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        XzEncoder::finish(*self).map(|_| ())
+    }
+}
+
+impl Finish for ZstdEncoder<'static, File> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        ZstdEncoder::finish(*self).map(|_| ())
+    }
+}
+
+/// The `.cargo-checksum.json` that cargo expects in a directory source
+#[derive(Serialize)]
+struct CrateChecksum {
+    files: BTreeMap<String, String>,
+    package: Option<String>,
+}
+
+/// The name of the vendor directory, which is also the directory the
+/// archive unpacks to in `${WORKDIR}`
+pub fn dir_name(dir: &Path) -> CargoResult<String> {
+    dir.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| {
+            anyhow!(
+                "Unable to vendor into '{}', it must be named",
+                dir.display()
+            )
+        })
+}
+
+/// The archive created alongside the vendor directory `dir`
+pub fn archive_path(dir: &Path, compression: Compression) -> CargoResult<PathBuf> {
+    Ok(dir.with_file_name(format!("{}.{}", dir_name(dir)?, compression.extension())))
+}
+
+/// Copies `packages` into `dir`, one directory per crate, and packs the
+/// result into the archive given by `archive_path`
+pub fn vendor(
+    config: &Config,
+    dir: &Path,
+    compression: Compression,
+    packages: &[&Package],
+    checksums: &HashMap<PackageId, Option<String>>,
+) -> CargoResult<PathBuf> {
+    for pkg in packages {
+        let id = pkg.package_id();
+        let dst = dir.join(format!("{}-{}", id.name(), id.version()));
+        if dst.exists() {
+            fs::remove_dir_all(&dst)
+                .map_err(|e| anyhow!("Unable to clean '{}': {}", dst.display(), e))?;
+        }
+
+        // only take the files cargo considers part of the package
+        let src = PathSource::new(pkg.root(), id.source_id(), config);
+        let mut files = BTreeMap::new();
+        for file in src.list_files(pkg)? {
+            let rel = file.strip_prefix(pkg.root())?;
+            if rel
+                .components()
+                .any(|c| SKIPPED_FILES.iter().any(|s| c.as_os_str() == *s))
+            {
+                continue;
+            }
+
+            let to = dst.join(rel);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| anyhow!("Unable to create '{}': {}", parent.display(), e))?;
+            }
+            fs::copy(&file, &to)
+                .map_err(|e| anyhow!("Unable to copy '{}': {}", file.display(), e))?;
+
+            let sha256 = checksum::file_sha256(&file)
+                .map_err(|e| anyhow!("Unable to checksum '{}': {}", file.display(), e))?;
+            files.insert(rel.to_string_lossy().replace('\\', "/"), sha256);
+        }
+
+        let cksum = CrateChecksum {
+            files,
+            package: checksums.get(&id).cloned().and_then(|c| c),
+        };
+        let cksum_path = dst.join(".cargo-checksum.json");
+        let cksum_file = File::create(&cksum_path)
+            .map_err(|e| anyhow!("Unable to create '{}': {}", cksum_path.display(), e))?;
+        serde_json::to_writer(cksum_file, &cksum)
+            .map_err(|e| anyhow!("Unable to write '{}': {}", cksum_path.display(), e))?;
+    }
+
+    // pack everything up so the recipe only has to fetch one file
+    let archive = archive_path(dir, compression)?;
+    let name = dir_name(dir)?;
+    let file = File::create(&archive)
+        .map_err(|e| anyhow!("Unable to create '{}': {}", archive.display(), e))?;
+    compression
+        .encoder(file)
+        .and_then(|encoder| {
+            let mut tar = tar::Builder::new(encoder);
+            tar.append_dir_all(&name, dir)?;
+            tar.into_inner()
+        })
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| anyhow!("Unable to write '{}': {}", archive.display(), e))?;
+
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn archive_next_to_dir() {
+        assert_eq!(
+            archive_path(Path::new("out/vendor"), Compression::Gz).unwrap(),
+            PathBuf::from("out/vendor.tar.gz")
+        );
+    }
+
+    #[test]
+    fn archive_dotted_dir() {
+        assert_eq!(
+            archive_path(Path::new("vendor-1.0"), Compression::Xz).unwrap(),
+            PathBuf::from("vendor-1.0.tar.xz")
+        );
+    }
+
+    #[test]
+    fn compression() {
+        assert_eq!("zst".parse(), Ok(Compression::Zst));
+        assert!("bz2".parse::<Compression>().is_err());
+        assert_eq!(Compression::Zst.extension(), "tar.zst");
+    }
+
+    #[test]
+    fn compressed_archive() {
+        let tmp = crate::testing::temp_dir();
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(dir.join("vendor")).unwrap();
+        fs::write(dir.join("vendor").join("file"), "contents\n").unwrap();
+
+        for &(compression, magic) in &[
+            (Compression::Gz, &[0x1f, 0x8b][..]),
+            (Compression::Xz, &[0xfd, b'7', b'z', b'X', b'Z'][..]),
+            (Compression::Zst, &[0x28, 0xb5, 0x2f, 0xfd][..]),
+        ] {
+            let file = File::create(dir.join("archive")).unwrap();
+            let mut tar = tar::Builder::new(compression.encoder(file).unwrap());
+            tar.append_dir_all("vendor", dir.join("vendor")).unwrap();
+            tar.into_inner().unwrap().finish().unwrap();
+
+            let archive = fs::read(dir.join("archive")).unwrap();
+            assert!(archive.starts_with(magic), "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn unnamed_dir() {
+        assert!(dir_name(Path::new("/")).is_err());
+    }
+}